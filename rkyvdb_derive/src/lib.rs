@@ -0,0 +1,115 @@
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, LitStr, Member, Type};
+
+/// Derives `rkyvdb::Collection`.
+///
+/// Accepts an optional `#[rkyvdb(...)]` attribute with:
+///
+/// - `cf = "..."`, the column family name, which defaults to the type name;
+/// - `key = "..."`, the field holding the key of each value. The key type is
+///   the type of the field, and a method `key` returns a reference to it;
+/// - `key_type = "..."`, the key type of collections whose values don't hold
+///   their key, which defaults to `()`.
+///
+/// ```
+/// use rkyvdb::Collection;
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Collection, Serialize, Deserialize)]
+/// #[rkyvdb(cf = "users", key = "id")]
+/// struct User {
+///     id: u64,
+///     name: String,
+/// }
+///
+/// #[derive(Collection, Serialize, Deserialize)]
+/// #[rkyvdb(key_type = "String")]
+/// struct Setting(u32);
+///
+/// let user = User { id: 7, name: "Ada".to_string() };
+/// let key: <User as Collection>::KeyType = *user.key();
+/// assert_eq!((User::CF_NAME, key), ("users", 7));
+/// let _: <Setting as Collection>::KeyType = String::new();
+/// assert_eq!(Setting::CF_NAME, "Setting");
+/// ```
+#[proc_macro_derive(Collection, attributes(rkyvdb))]
+pub fn derive_collection(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let mut cf: Option<LitStr> = None;
+    let mut key: Option<LitStr> = None;
+    let mut key_type: Option<Type> = None;
+    for attr in &input.attrs {
+        if !attr.path().is_ident("rkyvdb") {
+            continue;
+        }
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("cf") {
+                cf = Some(meta.value()?.parse()?);
+                Ok(())
+            } else if meta.path.is_ident("key") {
+                key = Some(meta.value()?.parse()?);
+                Ok(())
+            } else if meta.path.is_ident("key_type") {
+                key_type = Some(meta.value()?.parse::<LitStr>()?.parse()?);
+                Ok(())
+            } else {
+                Err(meta.error("unsupported rkyvdb attribute, expected `cf`, `key` or `key_type`"))
+            }
+        })?;
+    }
+
+    let name = &input.ident;
+    let cf = cf.unwrap_or_else(|| LitStr::new(&name.to_string(), name.span()));
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let (key_type, key_accessor) = match (key, key_type) {
+        (Some(key), Some(_)) => return Err(syn::Error::new(
+            key.span(),
+            "`key` already sets the key type, `key_type` is only for values without a key field",
+        )),
+        (Some(key), None) => {
+            let member: Member = key.parse()?;
+            let key_type = field_type(&input, &member)
+                .ok_or_else(|| syn::Error::new(key.span(), "no such field"))?;
+            let accessor = quote! {
+                impl #impl_generics #name #ty_generics #where_clause {
+                    /// Key the value is stored under.
+                    pub fn key(&self) -> &#key_type {
+                        &self.#member
+                    }
+                }
+            };
+            (key_type.clone(), accessor)
+        }
+        (None, key_type) => (key_type.unwrap_or_else(|| syn::parse_quote!(())), quote! {}),
+    };
+    Ok(quote! {
+        impl #impl_generics ::rkyvdb::Collection for #name #ty_generics #where_clause {
+            type KeyType = #key_type;
+            const CF_NAME: &'static str = #cf;
+        }
+
+        #key_accessor
+    })
+}
+
+/// Type of the field `member` of the struct `input`.
+fn field_type<'a>(input: &'a DeriveInput, member: &Member) -> Option<&'a Type> {
+    let Data::Struct(data) = &input.data else {
+        return None;
+    };
+    data.fields
+        .iter()
+        .enumerate()
+        .find(|(index, field)| match member {
+            Member::Named(name) => field.ident.as_ref() == Some(name),
+            Member::Unnamed(unnamed) => unnamed.index as usize == *index,
+        })
+        .map(|(_, field)| &field.ty)
+}
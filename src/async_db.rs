@@ -0,0 +1,153 @@
+use std::{
+    future,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use futures_core::Stream;
+use tokio::sync::mpsc;
+
+use crate::{Collection, Database, Error};
+
+/// Number of entries sent at once by the scans behind [`ScanStream`].
+const CHUNK_SIZE: usize = 256;
+/// Number of chunks a scan reads ahead of its consumer.
+const CHUNKS_AHEAD: usize = 2;
+
+/// Async wrapper around [`Database`] for tokio applications.
+///
+/// Every call is run on tokio's blocking thread pool, so RocksDB I/O never
+/// stalls the async workers. Must be used from within a tokio runtime.
+#[derive(Clone)]
+pub struct AsyncDatabase(Database);
+
+impl From<Database> for AsyncDatabase {
+    fn from(db: Database) -> Self {
+        Self(db)
+    }
+}
+
+impl AsyncDatabase {
+    pub fn database(&self) -> &Database {
+        &self.0
+    }
+
+    /// Runs `f` against the database on the blocking thread pool.
+    ///
+    /// Escape hatch for everything without a dedicated async method, such as
+    /// iteration, batches or transactions.
+    pub async fn run<R: Send + 'static>(
+        &self,
+        f: impl FnOnce(&Database) -> R + Send + 'static,
+    ) -> R {
+        let db = self.0.clone();
+        match tokio::task::spawn_blocking(move || f(&db)).await {
+            Ok(result) => result,
+            Err(err) => std::panic::resume_unwind(err.into_panic()),
+        }
+    }
+
+    pub async fn get<T>(&self, key: impl Into<T::KeyType>) -> Result<Option<T>, Error>
+    where
+        T: Collection + Send + 'static,
+        T::KeyType: Send + 'static,
+    {
+        let key: T::KeyType = key.into();
+        self.run(move |db| T::get(key, db)).await
+    }
+
+    pub async fn modify<T, R>(
+        &self,
+        key: impl Into<T::KeyType>,
+        modifier: impl FnOnce(&mut Option<T>) -> R + Send + 'static,
+    ) -> Result<R, Error>
+    where
+        T: Collection + Send + 'static,
+        T::KeyType: Send + 'static,
+        R: Send + 'static,
+    {
+        let key: T::KeyType = key.into();
+        self.run(move |db| T::modify(key, db, modifier)).await
+    }
+
+    /// Streams all entries of `T` whose key starts with `prefix`, in key
+    /// order.
+    ///
+    /// The scan runs on the blocking thread pool, reading ahead a bounded
+    /// number of entries: it waits whenever the consumer falls behind, and
+    /// stops once the stream is dropped.
+    pub fn stream_prefix<T>(&self, prefix: impl Into<T::KeyType>) -> ScanStream<T>
+    where
+        T: Collection + Send + 'static,
+        T::KeyType: Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel(CHUNKS_AHEAD);
+        let db = self.0.clone();
+        let prefix: T::KeyType = prefix.into();
+        tokio::task::spawn_blocking(move || {
+            let iter = match T::iter_prefix(prefix, &db) {
+                Ok(iter) => iter,
+                Err(err) => {
+                    let _ = sender.blocking_send(vec![Err(err)]);
+                    return;
+                }
+            };
+            let mut chunk = Vec::with_capacity(CHUNK_SIZE);
+            for item in iter {
+                chunk.push(item);
+                if chunk.len() == CHUNK_SIZE {
+                    let full = std::mem::replace(&mut chunk, Vec::with_capacity(CHUNK_SIZE));
+                    if sender.blocking_send(full).is_err() {
+                        return;
+                    }
+                }
+            }
+            if !chunk.is_empty() {
+                let _ = sender.blocking_send(chunk);
+            }
+        });
+        ScanStream {
+            receiver,
+            chunk: Vec::new().into_iter(),
+        }
+    }
+}
+
+type ScanItem<T> = Result<(<T as Collection>::KeyType, T), Error>;
+
+/// Entries of a scan started by [`AsyncDatabase::stream_prefix`], as a
+/// [`Stream`] usable with the combinators of the `futures` crates.
+pub struct ScanStream<T: Collection> {
+    receiver: mpsc::Receiver<Vec<ScanItem<T>>>,
+    chunk: std::vec::IntoIter<ScanItem<T>>,
+}
+
+// Nothing is pinned in place.
+impl<T: Collection> Unpin for ScanStream<T> {}
+
+impl<T: Collection> ScanStream<T> {
+    /// Returns the next entry, or `None` once the scan is over.
+    pub async fn next(&mut self) -> Option<ScanItem<T>> {
+        future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await
+    }
+}
+
+impl<T: Collection> Stream for ScanStream<T> {
+    type Item = ScanItem<T>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            if let Some(item) = self.chunk.next() {
+                return Poll::Ready(Some(item));
+            }
+            match ready!(self.receiver.poll_recv(cx)) {
+                Some(chunk) => self.chunk = chunk.into_iter(),
+                None => return Poll::Ready(None),
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.chunk.len(), None)
+    }
+}
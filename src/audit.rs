@@ -0,0 +1,249 @@
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use crate::{hash::fnv1a, ttl, CfOptions, Database, DatabaseBuilder, Error};
+
+/// Column family of the audit log, keyed by record sequence number.
+const CF_NAME: &str = "rkyvdb.audit";
+
+/// What the audit log records of written values.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuditValues {
+    /// A 64-bit FNV-1a hash of the stored value.
+    Hash,
+    /// The stored value itself.
+    Bytes,
+}
+
+/// Written value as recorded in the audit log. Values are recorded as
+/// stored, so encrypted values are recorded encrypted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AuditValue {
+    Deleted,
+    Hash(u64),
+    Bytes(Vec<u8>),
+}
+
+/// One write recorded in the audit log.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AuditRecord {
+    /// Position of the record in the log, increasing from 1.
+    pub sequence: u64,
+    /// Unix timestamp in seconds of the commit.
+    pub timestamp: u64,
+    pub collection: String,
+    /// Serialized key of the written entry.
+    pub key: Vec<u8>,
+    pub value: AuditValue,
+}
+
+/// Audit log state of a database opened with
+/// [`DatabaseBuilder::enable_audit_log`].
+pub(crate) struct AuditLog {
+    values: AuditValues,
+    /// Sequence number of the next record. Locked from when the records of
+    /// a commit are numbered until they are written, so that records reach
+    /// the log in sequence order and a failed commit leaves no gap.
+    next_sequence: Mutex<u64>,
+}
+
+/// Records staged by [`AuditLog::stage`], holding the lock of the sequence
+/// numbers until the batch with them is written.
+pub(crate) struct StagedRecords<'a> {
+    next_sequence: MutexGuard<'a, u64>,
+    count: u64,
+}
+
+impl AuditLog {
+    pub(crate) fn open(rocksdb: &rocksdb::DB, values: AuditValues) -> Result<Self, Error> {
+        let cf = rocksdb
+            .cf_handle(CF_NAME)
+            .ok_or(Error::CollectionNotRegistered)?;
+        let mut raw = rocksdb.raw_iterator_cf(&cf);
+        raw.seek_to_last();
+        let last_sequence = match raw.key() {
+            Some(key) => decode_u64(key)?,
+            None => {
+                raw.status()?;
+                0
+            }
+        };
+        Ok(AuditLog {
+            values,
+            next_sequence: Mutex::new(last_sequence + 1),
+        })
+    }
+
+    /// Stages the records of `writes`, as `(collection, key, value)` with
+    /// `None` for a delete. The batch must be written before the returned
+    /// records are dropped, and [`StagedRecords::committed`] called once it
+    /// is.
+    pub(crate) fn stage<'k>(
+        &self,
+        rocksdb: &rocksdb::DB,
+        batch: &mut rocksdb::WriteBatch,
+        writes: impl IntoIterator<Item = (&'k str, &'k [u8], Option<&'k [u8]>)>,
+    ) -> Result<StagedRecords<'_>, Error> {
+        let cf = rocksdb
+            .cf_handle(CF_NAME)
+            .ok_or(Error::CollectionNotRegistered)?;
+        let mut staged = StagedRecords {
+            // Numbers are only taken once the batch is written.
+            next_sequence: self
+                .next_sequence
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner),
+            count: 0,
+        };
+        for (cf_name, key, value) in writes {
+            let sequence = *staged.next_sequence + staged.count;
+            batch.put_cf(
+                &cf,
+                sequence.to_be_bytes(),
+                self.record(cf_name, key, value),
+            );
+            staged.count += 1;
+        }
+        Ok(staged)
+    }
+
+    fn record(&self, cf_name: &str, key: &[u8], value: Option<&[u8]>) -> Vec<u8> {
+        let mut record = Vec::new();
+        record.extend_from_slice(&ttl::now().to_be_bytes());
+        for bytes in [cf_name.as_bytes(), key] {
+            record.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+            record.extend_from_slice(bytes);
+        }
+        match (value, self.values) {
+            (None, _) => record.push(0),
+            (Some(value), AuditValues::Hash) => {
+                record.push(1);
+                record.extend_from_slice(&fnv1a(value).to_be_bytes());
+            }
+            (Some(value), AuditValues::Bytes) => {
+                record.push(2);
+                record.extend_from_slice(value);
+            }
+        }
+        record
+    }
+}
+
+impl StagedRecords<'_> {
+    /// Takes the sequence numbers of the records, once they are written.
+    pub(crate) fn committed(mut self) {
+        *self.next_sequence += self.count;
+    }
+}
+
+impl DatabaseBuilder {
+    /// Records every write committed by collections, indexes excepted, in an
+    /// audit log read with [`Database::audit_log`]. Records are written in
+    /// the same batch as the writes they describe. Blob, versioned and merge
+    /// collections, bulk loads and range deletions write around it.
+    ///
+    /// Records are numbered in commit order without gaps, so the log can be
+    /// tailed by reading on from the last sequence number seen. This
+    /// serializes commits while their records are numbered and written.
+    pub fn enable_audit_log(mut self, values: AuditValues) -> Self {
+        self.audit = Some(values);
+        self.cf_descriptors.push((
+            CF_NAME.to_string(),
+            CfOptions::Default {
+                collection: |_| {},
+                configure: |_| {},
+                prefix_bloom: false,
+            },
+        ));
+        self
+    }
+}
+
+impl Database {
+    /// Iterates over the audit log from its oldest record.
+    pub fn audit_log(&self) -> Result<AuditIter<'_>, Error> {
+        self.audit_log_from(0)
+    }
+
+    /// Iterates over the audit log from the record with sequence number
+    /// `sequence` on.
+    pub fn audit_log_from(&self, sequence: u64) -> Result<AuditIter<'_>, Error> {
+        let cf = self
+            .rocksdb
+            .cf_handle(CF_NAME)
+            .ok_or(Error::CollectionNotRegistered)?;
+        let mut raw = self.rocksdb.raw_iterator_cf(&cf);
+        raw.seek(sequence.to_be_bytes());
+        Ok(AuditIter { raw, done: false })
+    }
+}
+
+/// Iterator over the records of the audit log, created by
+/// [`Database::audit_log`].
+pub struct AuditIter<'db> {
+    raw: rocksdb::DBRawIterator<'db>,
+    done: bool,
+}
+
+impl<'db> Iterator for AuditIter<'db> {
+    type Item = Result<AuditRecord, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let (key, value) = match (self.raw.key(), self.raw.value()) {
+            (Some(key), Some(value)) => (key, value),
+            _ => {
+                self.done = true;
+                return self.raw.status().err().map(|err| Err(err.into()));
+            }
+        };
+        let record = decode_record(key, value);
+        self.raw.next();
+        Some(record)
+    }
+}
+
+fn decode_record(key: &[u8], mut record: &[u8]) -> Result<AuditRecord, Error> {
+    let timestamp = decode_u64(take(&mut record, 8)?)?;
+    let collection_len = decode_u32(take(&mut record, 4)?)?;
+    let collection = String::from_utf8(take(&mut record, collection_len as usize)?.to_vec())
+        .map_err(|_| Error::CorruptMetadata)?;
+    let key_len = decode_u32(take(&mut record, 4)?)?;
+    let entry_key = take(&mut record, key_len as usize)?.to_vec();
+    let value = match take(&mut record, 1)? {
+        [0] => AuditValue::Deleted,
+        [1] => AuditValue::Hash(decode_u64(record)?),
+        [2] => AuditValue::Bytes(record.to_vec()),
+        _ => return Err(Error::CorruptMetadata),
+    };
+    Ok(AuditRecord {
+        sequence: decode_u64(key)?,
+        timestamp,
+        collection,
+        key: entry_key,
+        value,
+    })
+}
+
+/// Splits the first `len` bytes off `bytes`.
+fn take<'a>(bytes: &mut &'a [u8], len: usize) -> Result<&'a [u8], Error> {
+    if bytes.len() < len {
+        return Err(Error::CorruptMetadata);
+    }
+    let (taken, rest) = bytes.split_at(len);
+    *bytes = rest;
+    Ok(taken)
+}
+
+fn decode_u64(bytes: &[u8]) -> Result<u64, Error> {
+    Ok(u64::from_be_bytes(
+        bytes.try_into().map_err(|_| Error::CorruptMetadata)?,
+    ))
+}
+
+fn decode_u32(bytes: &[u8]) -> Result<u32, Error> {
+    Ok(u32::from_be_bytes(
+        bytes.try_into().map_err(|_| Error::CorruptMetadata)?,
+    ))
+}
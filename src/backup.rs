@@ -0,0 +1,72 @@
+use rocksdb::backup::{BackupEngine, BackupEngineOptions, RestoreOptions};
+
+pub use rocksdb::backup::BackupEngineInfo as BackupInfo;
+
+use crate::{Database, Error};
+
+fn open_engine(backup_path: &str) -> Result<BackupEngine, Error> {
+    Ok(BackupEngine::open(
+        &BackupEngineOptions::default(),
+        backup_path,
+    )?)
+}
+
+impl Database {
+    /// Creates a consistent copy of the live database in `path`, which must
+    /// not exist yet. SST files are hard-linked when `path` is on the same
+    /// filesystem, so this is cheap even for large databases.
+    pub fn checkpoint(&self, path: &str) -> Result<(), Error> {
+        rocksdb::checkpoint::Checkpoint::new(&self.rocksdb)?.create_checkpoint(path)?;
+        Ok(())
+    }
+
+    /// Adds a new backup of the live database to the backup directory
+    /// `backup_path`, flushing memtables first.
+    ///
+    /// Backups are incremental: files already stored by an earlier backup in
+    /// the same directory are shared rather than copied again.
+    pub fn backup_to(&self, backup_path: &str) -> Result<(), Error> {
+        open_engine(backup_path)?.create_new_backup_flush(&self.rocksdb, true)?;
+        Ok(())
+    }
+
+    /// Lists the backups stored in `backup_path`, oldest first.
+    pub fn list_backups(backup_path: &str) -> Result<Vec<BackupInfo>, Error> {
+        Ok(open_engine(backup_path)?.get_backup_info())
+    }
+
+    /// Checks that the files of backup `backup_id` are present and intact.
+    pub fn verify_backup(backup_path: &str, backup_id: u32) -> Result<(), Error> {
+        open_engine(backup_path)?.verify_backup(backup_id)?;
+        Ok(())
+    }
+
+    /// Deletes all but the `keep` most recent backups in `backup_path`.
+    pub fn purge_old_backups(backup_path: &str, keep: usize) -> Result<(), Error> {
+        open_engine(backup_path)?.purge_old_backups(keep)?;
+        Ok(())
+    }
+
+    /// Restores the latest backup in `backup_path` into `db_path`, which must
+    /// not be open.
+    pub fn restore_from(backup_path: &str, db_path: &str) -> Result<(), Error> {
+        open_engine(backup_path)?.restore_from_latest_backup(
+            db_path,
+            db_path,
+            &RestoreOptions::default(),
+        )?;
+        Ok(())
+    }
+
+    /// Restores backup `backup_id` from `backup_path` into `db_path`, which
+    /// must not be open.
+    pub fn restore_backup(backup_path: &str, backup_id: u32, db_path: &str) -> Result<(), Error> {
+        open_engine(backup_path)?.restore_from_backup(
+            db_path,
+            db_path,
+            &RestoreOptions::default(),
+            backup_id,
+        )?;
+        Ok(())
+    }
+}
@@ -0,0 +1,89 @@
+use crate::{
+    value,
+    write::{self, Durability, PendingWrite},
+    Collection, Database, Error, Key,
+};
+
+/// Set of writes across collections that are committed atomically.
+///
+/// Created by [`Database::batch`]. Errors are deferred: the first failed `put`
+/// or `delete` is reported by [`WriteBatch::commit`] and nothing is written.
+#[must_use = "a batch does nothing until it is committed"]
+pub struct WriteBatch<'db> {
+    db: &'db Database,
+    writes: Vec<PendingWrite>,
+    error: Option<Error>,
+    durability: Durability,
+}
+
+impl Database {
+    pub fn batch(&self) -> WriteBatch<'_> {
+        WriteBatch {
+            db: self,
+            writes: Vec::new(),
+            error: None,
+            durability: Durability::Default,
+        }
+    }
+}
+
+impl<'db> WriteBatch<'db> {
+    pub fn put<T: Collection>(mut self, key: impl Into<T::KeyType>, value: &T) -> Self {
+        if self.error.is_none() {
+            match PendingWrite::put(&key.into().serialize(), value) {
+                Ok(write) => self.writes.push(write),
+                Err(err) => self.error = Some(err),
+            }
+        }
+        self
+    }
+
+    pub fn delete<T: Collection>(mut self, key: impl Into<T::KeyType>) -> Self {
+        if self.error.is_none() {
+            self.writes
+                .push(PendingWrite::delete::<T>(&key.into().serialize()));
+        }
+        self
+    }
+
+    /// Reads the value of `T` stored under `key` as if the batch was already
+    /// committed: the last write to `key` staged in the batch, or else the
+    /// value in the database.
+    pub fn get<T: Collection>(&self, key: impl Into<T::KeyType>) -> Result<Option<T>, Error> {
+        let key: T::KeyType = key.into();
+        let key = key.serialize();
+        match write::staged(&self.writes, T::CF_NAME, &key) {
+            Some(staged) => staged.map(|staged| value::decode(&key, staged)).transpose(),
+            None => self.db.get_serialized(&key),
+        }
+    }
+
+    /// Commits the batch with `durability` instead of
+    /// [`Durability::Default`].
+    pub fn durability(mut self, durability: Durability) -> Self {
+        self.durability = durability;
+        self
+    }
+
+    /// Number of writes staged so far.
+    pub fn len(&self) -> usize {
+        self.writes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.writes.is_empty()
+    }
+
+    pub fn commit(self) -> Result<(), Error> {
+        if let Some(err) = self.error {
+            return Err(err);
+        }
+        // Serialize with `modify` and transaction commits so they never see
+        // the batch half-way through their read-modify-write.
+        let _guard = self
+            .db
+            .locks
+            .lock_many(self.writes.iter().map(PendingWrite::lock_key));
+        write::commit_opt(self.db, self.writes, self.durability)
+    }
+}
@@ -0,0 +1,266 @@
+//! Administration of rkyvdb databases without their collection types.
+//!
+//! Keys are given as UTF-8 strings, or as hex with a `0x` prefix. Values are
+//! printed as hex, or decoded from MessagePack to JSON by `get --json` and
+//! `dump`. Without the collection types, structs come out as arrays of their
+//! fields and encrypted values can't be decoded at all; use
+//! [`rkyvdb::Collection::export_json`] from a program that has them. Merge
+//! operands can't be read or compacted here, as the merge operators live in
+//! those types too.
+//!
+//! Deleting keys here skips the indexes and the audit log of their
+//! collection, so `delete` refuses to run on collections with indexes. The
+//! database doesn't record which column families are indexes, so any named
+//! after the collection and a dot count as one: a collection `users` can't
+//! be deleted from here while a collection `users.archive` exists.
+//!
+//! Neither do column families ordered by a custom
+//! [`rkyvdb::Collection::COMPARATOR`] open here. Reads open the database
+//! without them, and commands that need every column family refuse to run
+//! on a database that has some.
+
+use std::{error::Error, fmt::Write, fs};
+
+use rocksdb::{
+    backup::{BackupEngine, BackupEngineOptions},
+    Options, DB,
+};
+
+const USAGE: &str = "\
+usage: rkyvdb-cli <db path> <command>
+
+commands:
+    list                     list the column families and custom comparators
+    count <cf>               count the keys of a column family
+    get [--json] <cf> <key>  print the raw value of a key, or decode it to JSON
+    dump <cf>                print every entry as a line of JSON
+    delete <cf> <key>...     delete keys of a collection without indexes
+    compact [<cf>]           compact a column family, or all of them
+    backup <backup path>     add a backup of the database to a backup directory";
+
+fn parse_key(key: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    let Some(hex) = key.strip_prefix("0x") else {
+        return Ok(key.as_bytes().to_vec());
+    };
+    if hex.len() % 2 != 0 {
+        return Err("hex key has an odd number of digits".into());
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            hex.get(i..i + 2)
+                .filter(|byte| byte.bytes().all(|digit| digit.is_ascii_hexdigit()))
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                .ok_or_else(|| format!("invalid hex key {:?}", key).into())
+        })
+        .collect()
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{:02x}", byte);
+        hex
+    })
+}
+
+/// First byte of values stamped with the version of their collection,
+/// followed by the version as a big-endian `u32`.
+const VERSION_MARKER: u8 = 0xc1;
+
+/// Decodes a stored value to JSON.
+fn to_json(value: &[u8]) -> Result<serde_json::Value, Box<dyn Error>> {
+    let body = match value {
+        [VERSION_MARKER, _, _, _, _, body @ ..] => body,
+        _ => value,
+    };
+    rmp_serde::from_slice(body)
+        .map_err(|err| format!("value can't be decoded, it may be encrypted: {}", err).into())
+}
+
+/// Name RocksDB records for its default byte order comparator.
+const BYTEWISE_COMPARATOR: &str = "leveldb.BytewiseComparator";
+
+/// Column families of the database at `path` ordered by a custom comparator,
+/// with the name of the comparator, as recorded in its latest OPTIONS file.
+fn custom_comparators(path: &str) -> Result<Vec<(String, String)>, Box<dyn Error>> {
+    let mut latest: Option<(u64, std::path::PathBuf)> = None;
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let Some(number) = name.to_str().and_then(|name| name.strip_prefix("OPTIONS-")) else {
+            continue;
+        };
+        let Ok(number) = number.parse::<u64>() else {
+            continue;
+        };
+        if latest.as_ref().is_none_or(|(latest, _)| number > *latest) {
+            latest = Some((number, entry.path()));
+        }
+    }
+    let Some((_, options_path)) = latest else {
+        return Ok(Vec::new());
+    };
+    let mut custom = Vec::new();
+    let mut cf_name = None;
+    for line in fs::read_to_string(options_path)?.lines() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            cf_name = line
+                .strip_prefix("[CFOptions \"")
+                .and_then(|line| line.strip_suffix("\"]"))
+                .map(str::to_string);
+        } else if let (Some(cf_name), Some(comparator)) =
+            (&cf_name, line.strip_prefix("comparator="))
+        {
+            if comparator != BYTEWISE_COMPARATOR {
+                custom.push((cf_name.clone(), comparator.to_string()));
+            }
+        }
+    }
+    Ok(custom)
+}
+
+/// Opens the database at `path` with every column family, which fails if
+/// some of them are ordered by custom comparators.
+fn open(path: &str) -> Result<DB, Box<dyn Error>> {
+    let custom = custom_comparators(path)?;
+    if !custom.is_empty() {
+        let names: Vec<String> = custom
+            .iter()
+            .map(|(cf_name, comparator)| format!("{} ({})", cf_name, comparator))
+            .collect();
+        return Err(format!(
+            "this command needs every column family, but {} use custom comparators",
+            names.join(", ")
+        )
+        .into());
+    }
+    let cf_names = DB::list_cf(&Options::default(), path)?;
+    Ok(DB::open_cf(&Options::default(), path, cf_names)?)
+}
+
+/// Opens the database at `path` read-only to read `cf_name`, leaving out the
+/// column families ordered by custom comparators.
+fn open_to_read(path: &str, cf_name: &str) -> Result<DB, Box<dyn Error>> {
+    let custom = custom_comparators(path)?;
+    if let Some((_, comparator)) = custom.iter().find(|(custom, _)| custom == cf_name) {
+        return Err(format!(
+            "column family {:?} is ordered by the custom comparator {}",
+            cf_name, comparator
+        )
+        .into());
+    }
+    let cf_names: Vec<String> = DB::list_cf(&Options::default(), path)?
+        .into_iter()
+        .filter(|name| custom.iter().all(|(custom, _)| custom != name))
+        .collect();
+    Ok(DB::open_cf_for_read_only(
+        &Options::default(),
+        path,
+        cf_names,
+        false,
+    )?)
+}
+
+fn cf<'db>(db: &'db DB, name: &str) -> Result<rocksdb::ColumnFamilyRef<'db>, Box<dyn Error>> {
+    db.cf_handle(name)
+        .ok_or_else(|| format!("no column family {:?}", name).into())
+}
+
+fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let [path, command, args @ ..] = args else {
+        return Err(USAGE.into());
+    };
+    match (command.as_str(), args) {
+        ("list", []) => {
+            let custom = custom_comparators(path)?;
+            for name in DB::list_cf(&Options::default(), path)? {
+                match custom.iter().find(|(custom, _)| *custom == name) {
+                    Some((_, comparator)) => println!("{} (comparator {})", name, comparator),
+                    None => println!("{}", name),
+                }
+            }
+        }
+        ("count", [cf_name]) => {
+            let db = open_to_read(path, cf_name)?;
+            let mut raw = db.raw_iterator_cf(&cf(&db, cf_name)?);
+            raw.seek_to_first();
+            let mut count = 0u64;
+            while raw.valid() {
+                count += 1;
+                raw.next();
+            }
+            raw.status()?;
+            println!("{}", count);
+        }
+        ("get", [cf_name, key]) => {
+            let db = open_to_read(path, cf_name)?;
+            let value = db.get_pinned_cf(&cf(&db, cf_name)?, parse_key(key)?)?;
+            println!("{}", to_hex(&value.ok_or("key not found")?));
+        }
+        ("get", [flag, cf_name, key]) if flag == "--json" => {
+            let db = open_to_read(path, cf_name)?;
+            let value = db.get_pinned_cf(&cf(&db, cf_name)?, parse_key(key)?)?;
+            let value = to_json(&value.ok_or("key not found")?)?;
+            println!("{}", serde_json::to_string_pretty(&value)?);
+        }
+        ("dump", [cf_name]) => {
+            let db = open_to_read(path, cf_name)?;
+            let mut raw = db.raw_iterator_cf(&cf(&db, cf_name)?);
+            raw.seek_to_first();
+            while let (Some(key), Some(value)) = (raw.key(), raw.value()) {
+                let entry = serde_json::json!({ "key": to_hex(key), "value": to_json(value)? });
+                println!("{}", entry);
+                raw.next();
+            }
+            raw.status()?;
+        }
+        ("delete", [cf_name, keys @ ..]) if !keys.is_empty() => {
+            let index_prefix = format!("{}.", cf_name);
+            let indexes: Vec<String> = DB::list_cf(&Options::default(), path)?
+                .into_iter()
+                .filter(|name| name.starts_with(&index_prefix))
+                .collect();
+            if !indexes.is_empty() {
+                return Err(format!(
+                    "deleting keys of {:?} would leave its indexes {} stale",
+                    cf_name,
+                    indexes.join(", ")
+                )
+                .into());
+            }
+            let db = open(path)?;
+            let cf = cf(&db, cf_name)?;
+            let mut batch = rocksdb::WriteBatch::default();
+            for key in keys {
+                batch.delete_cf(&cf, parse_key(key)?);
+            }
+            db.write(batch)?;
+        }
+        ("compact", []) => {
+            let db = open(path)?;
+            for name in DB::list_cf(&Options::default(), path)? {
+                db.compact_range_cf(&cf(&db, &name)?, None::<&[u8]>, None::<&[u8]>);
+            }
+        }
+        ("compact", [cf_name]) => {
+            let db = open(path)?;
+            db.compact_range_cf(&cf(&db, cf_name)?, None::<&[u8]>, None::<&[u8]>);
+        }
+        ("backup", [backup_path]) => {
+            let db = open(path)?;
+            BackupEngine::open(&BackupEngineOptions::default(), backup_path)?
+                .create_new_backup_flush(&db, true)?;
+        }
+        _ => return Err(USAGE.into()),
+    }
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Err(err) = run(&args) {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,383 @@
+use std::io::{self, Read, Write};
+
+use crate::{key, lock::LockGuard, CfOptions, Database, DatabaseBuilder, Error, Key};
+
+/// Collection of large byte values, which are split into chunks stored under
+/// separate keys so that no single write or read has to hold a whole value.
+///
+/// Values are written with a [`BlobWriter`] and read with a [`BlobReader`],
+/// or whole with [`BlobCollection::put`] and [`BlobCollection::get`]. A value
+/// being written stays invisible until [`BlobWriter::finish`], and readers
+/// keep seeing the value they started with. The collection has to be
+/// registered with [`DatabaseBuilder::add_blob_collection`].
+pub trait BlobCollection {
+    type KeyType: Key;
+    const CF_NAME: &'static str;
+    /// Size in bytes of the chunks values are split into.
+    const CHUNK_SIZE: usize = 1 << 20;
+
+    /// Stores `value` under `key`, replacing any previous value.
+    fn put<K: Into<Self::KeyType>>(key: K, value: &[u8], db: &Database) -> Result<(), Error> {
+        let mut writer = Self::writer(key, db)?;
+        writer.write_all(value)?;
+        writer.finish()
+    }
+
+    /// Reads the whole value stored under `key`.
+    fn get<K: Into<Self::KeyType>>(key: K, db: &Database) -> Result<Option<Vec<u8>>, Error> {
+        let Some(mut reader) = Self::reader(key, db)? else {
+            return Ok(None);
+        };
+        let mut value = Vec::with_capacity(reader.len() as usize);
+        reader.read_to_end(&mut value)?;
+        Ok(Some(value))
+    }
+
+    /// Starts writing a new value for `key`. The key is locked until the
+    /// writer is finished or dropped.
+    fn writer<K: Into<Self::KeyType>>(key: K, db: &Database) -> Result<BlobWriter<'_>, Error> {
+        let prefix = prefix(&key.into().serialize());
+        let guard = db.locks.lock(Self::CF_NAME, &prefix);
+        let old_generation = read_head(db, Self::CF_NAME, &prefix)?.map(|head| head.generation);
+        Ok(BlobWriter {
+            db,
+            cf_name: Self::CF_NAME,
+            generation: old_generation.map_or(0, |generation| generation + 1),
+            old_generation,
+            prefix,
+            chunk_size: Self::CHUNK_SIZE,
+            buffer: Vec::new(),
+            chunks: 0,
+            len: 0,
+            finished: false,
+            _guard: guard,
+        })
+    }
+
+    /// Starts reading the value stored under `key`, if any.
+    fn reader<K: Into<Self::KeyType>>(
+        key: K,
+        db: &Database,
+    ) -> Result<Option<BlobReader<'_>>, Error> {
+        let prefix = prefix(&key.into().serialize());
+        let snapshot = db.rocksdb.snapshot();
+        let cf = cf_handle(db, Self::CF_NAME)?;
+        let Some(head) = snapshot.get_cf(&cf, head_key(&prefix))? else {
+            return Ok(None);
+        };
+        let head = Head::decode(&head)?;
+        Ok(Some(BlobReader {
+            snapshot,
+            cf,
+            prefix,
+            head,
+            chunk: Vec::new(),
+            offset: 0,
+            next_chunk: 0,
+        }))
+    }
+
+    /// Size in bytes of the value stored under `key`.
+    fn len<K: Into<Self::KeyType>>(key: K, db: &Database) -> Result<Option<u64>, Error> {
+        let prefix = prefix(&key.into().serialize());
+        Ok(read_head(db, Self::CF_NAME, &prefix)?.map(|head| head.len))
+    }
+
+    /// Removes the value stored under `key`, if any.
+    fn delete<K: Into<Self::KeyType>>(key: K, db: &Database) -> Result<(), Error> {
+        let prefix = prefix(&key.into().serialize());
+        let _guard = db.locks.lock(Self::CF_NAME, &prefix);
+        let cf = cf_handle(db, Self::CF_NAME)?;
+        if let Some(head) = read_head(db, Self::CF_NAME, &prefix)? {
+            let mut batch = rocksdb::WriteBatch::default();
+            batch.delete_cf(&cf, head_key(&prefix));
+            let (start, end) = generation_range(&prefix, head.generation);
+            batch.delete_range_cf(&cf, start, end);
+            db.rocksdb.write(batch)?;
+        }
+        Ok(())
+    }
+}
+
+impl DatabaseBuilder {
+    pub fn add_blob_collection<B: BlobCollection>(mut self) -> Self {
+        self.cf_descriptors.push((
+            B::CF_NAME.to_string(),
+            CfOptions::Default {
+                collection: |_| {},
+                configure: |_| {},
+                prefix_bloom: false,
+            },
+        ));
+        self
+    }
+}
+
+/// Writer of a new value of a [`BlobCollection`], created by
+/// [`BlobCollection::writer`].
+///
+/// Chunks are stored as they fill up, under a new generation of the key, and
+/// the value only replaces the previous one on [`BlobWriter::finish`]. The
+/// chunks of a writer dropped before that are deleted.
+#[must_use = "a blob is only stored once the writer is finished"]
+pub struct BlobWriter<'db> {
+    db: &'db Database,
+    cf_name: &'static str,
+    prefix: Vec<u8>,
+    generation: u64,
+    old_generation: Option<u64>,
+    chunk_size: usize,
+    buffer: Vec<u8>,
+    chunks: u32,
+    len: u64,
+    finished: bool,
+    _guard: LockGuard<'db>,
+}
+
+impl<'db> BlobWriter<'db> {
+    /// Stores the last chunk and replaces the previous value with the written
+    /// one.
+    pub fn finish(mut self) -> Result<(), Error> {
+        if !self.buffer.is_empty() {
+            self.write_chunk()?;
+        }
+        let cf = cf_handle(self.db, self.cf_name)?;
+        let mut batch = rocksdb::WriteBatch::default();
+        let head = Head {
+            generation: self.generation,
+            len: self.len,
+        };
+        batch.put_cf(&cf, head_key(&self.prefix), head.encode());
+        if let Some(old_generation) = self.old_generation {
+            let (start, end) = generation_range(&self.prefix, old_generation);
+            batch.delete_range_cf(&cf, start, end);
+        }
+        self.db.rocksdb.write(batch)?;
+        self.finished = true;
+        Ok(())
+    }
+
+    fn write_chunk(&mut self) -> Result<(), Error> {
+        let cf = cf_handle(self.db, self.cf_name)?;
+        let size = self.buffer.len().min(self.chunk_size);
+        self.db.rocksdb.put_cf(
+            &cf,
+            chunk_key(&self.prefix, self.generation, self.chunks),
+            &self.buffer[..size],
+        )?;
+        self.buffer.drain(..size);
+        self.chunks += 1;
+        Ok(())
+    }
+}
+
+impl<'db> Write for BlobWriter<'db> {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(bytes);
+        self.len += bytes.len() as u64;
+        while self.buffer.len() >= self.chunk_size {
+            self.write_chunk().map_err(io::Error::other)?;
+        }
+        Ok(bytes.len())
+    }
+
+    /// Does nothing: partial chunks are only stored by
+    /// [`BlobWriter::finish`].
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'db> Drop for BlobWriter<'db> {
+    fn drop(&mut self) {
+        if !self.finished {
+            if let Ok(cf) = cf_handle(self.db, self.cf_name) {
+                let (start, end) = generation_range(&self.prefix, self.generation);
+                let _ = self.db.rocksdb.delete_range_cf(&cf, start, end);
+            }
+        }
+    }
+}
+
+/// Reader of a value of a [`BlobCollection`], created by
+/// [`BlobCollection::reader`]. Reads from a snapshot, so later writes to the
+/// key don't affect it.
+pub struct BlobReader<'db> {
+    snapshot: rocksdb::Snapshot<'db>,
+    cf: rocksdb::ColumnFamilyRef<'db>,
+    prefix: Vec<u8>,
+    head: Head,
+    chunk: Vec<u8>,
+    /// Position of the next byte to read in `chunk`.
+    offset: usize,
+    next_chunk: u32,
+}
+
+impl<'db> BlobReader<'db> {
+    /// Size in bytes of the whole value.
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> u64 {
+        self.head.len
+    }
+}
+
+impl<'db> Read for BlobReader<'db> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.offset == self.chunk.len() {
+            let key = chunk_key(&self.prefix, self.head.generation, self.next_chunk);
+            match self
+                .snapshot
+                .get_cf(&self.cf, key)
+                .map_err(io::Error::other)?
+            {
+                Some(chunk) => {
+                    self.chunk = chunk;
+                    self.offset = 0;
+                    self.next_chunk += 1;
+                }
+                None => return Ok(0),
+            }
+        }
+        let size = buf.len().min(self.chunk.len() - self.offset);
+        buf[..size].copy_from_slice(&self.chunk[self.offset..self.offset + size]);
+        self.offset += size;
+        Ok(size)
+    }
+}
+
+/// Location and size of the current value of a key.
+struct Head {
+    generation: u64,
+    len: u64,
+}
+
+impl Head {
+    fn encode(&self) -> [u8; 16] {
+        let mut bytes = [0; 16];
+        bytes[..8].copy_from_slice(&self.generation.to_be_bytes());
+        bytes[8..].copy_from_slice(&self.len.to_be_bytes());
+        bytes
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, Error> {
+        let bytes: [u8; 16] = bytes.try_into().map_err(|_| Error::CorruptMetadata)?;
+        let (generation, len) = bytes.split_at(8);
+        Ok(Head {
+            generation: u64::from_be_bytes(generation.try_into().unwrap()),
+            len: u64::from_be_bytes(len.try_into().unwrap()),
+        })
+    }
+}
+
+/// Keys of a blob start with its encoded key, which no other encoded key
+/// starts with, followed by `0` for the head or by `1`, the generation and
+/// the index for chunks.
+fn prefix(key: &[u8]) -> Vec<u8> {
+    let mut prefix = Vec::new();
+    key::encode_component(&mut prefix, key);
+    prefix
+}
+
+fn head_key(prefix: &[u8]) -> Vec<u8> {
+    [prefix, &[0]].concat()
+}
+
+fn chunk_key(prefix: &[u8], generation: u64, index: u32) -> Vec<u8> {
+    [
+        prefix,
+        &[1],
+        &generation.to_be_bytes(),
+        &index.to_be_bytes(),
+    ]
+    .concat()
+}
+
+/// Range of the keys of every chunk of `generation`.
+fn generation_range(prefix: &[u8], generation: u64) -> (Vec<u8>, Vec<u8>) {
+    let start = [prefix, &[1], &generation.to_be_bytes()].concat();
+    // Greater than every 4-byte chunk index.
+    let end = [&start[..], &[u8::MAX; 5]].concat();
+    (start, end)
+}
+
+fn read_head(db: &Database, cf_name: &str, prefix: &[u8]) -> Result<Option<Head>, Error> {
+    db.rocksdb
+        .get_pinned_cf(&cf_handle(db, cf_name)?, head_key(prefix))?
+        .map(|head| Head::decode(&head))
+        .transpose()
+}
+
+fn cf_handle<'db>(
+    db: &'db Database,
+    cf_name: &str,
+) -> Result<rocksdb::ColumnFamilyRef<'db>, Error> {
+    db.rocksdb
+        .cf_handle(cf_name)
+        .ok_or(Error::CollectionNotRegistered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct File;
+
+    impl BlobCollection for File {
+        type KeyType = String;
+        const CF_NAME: &'static str = "files";
+        const CHUNK_SIZE: usize = 4;
+    }
+
+    fn open() -> Database {
+        Database::build()
+            .add_blob_collection::<File>()
+            .open_temp()
+            .unwrap()
+    }
+
+    #[test]
+    fn values_are_reassembled_from_their_chunks() {
+        let db = open();
+        let value: Vec<u8> = (0..=41).collect();
+        File::put("a", &value, &db).unwrap();
+        assert_eq!(File::len("a", &db).unwrap(), Some(42));
+        assert_eq!(File::get("a", &db).unwrap(), Some(value.clone()));
+
+        // Reads across chunk boundaries.
+        let mut reader = File::reader("a", &db).unwrap().unwrap();
+        let mut read = Vec::new();
+        let mut buf = [0; 3];
+        loop {
+            match reader.read(&mut buf).unwrap() {
+                0 => break,
+                n => read.extend_from_slice(&buf[..n]),
+            }
+        }
+        assert_eq!(read, value);
+
+        File::put("a", b"short", &db).unwrap();
+        assert_eq!(File::get("a", &db).unwrap(), Some(b"short".to_vec()));
+        File::delete("a", &db).unwrap();
+        assert_eq!(File::get("a", &db).unwrap(), None);
+    }
+
+    #[test]
+    fn readers_keep_the_value_they_started_with() {
+        let db = open();
+        File::put("a", b"first value", &db).unwrap();
+        let mut reader = File::reader("a", &db).unwrap().unwrap();
+        File::put("a", b"second", &db).unwrap();
+        let mut read = Vec::new();
+        reader.read_to_end(&mut read).unwrap();
+        assert_eq!(read, b"first value");
+    }
+
+    #[test]
+    fn unfinished_values_stay_invisible() {
+        let db = open();
+        let mut writer = File::writer("a", &db).unwrap();
+        writer.write_all(b"never finished").unwrap();
+        drop(writer);
+        assert_eq!(File::get("a", &db).unwrap(), None);
+    }
+}
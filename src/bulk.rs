@@ -0,0 +1,110 @@
+use std::{cmp::Ordering, marker::PhantomData, path::PathBuf};
+
+use crate::{
+    index, set_collection_options, temp::TempDir, value, Collection, Comparator, Database, Error,
+    Key, Options,
+};
+
+/// Bytes of keys and values buffered before they are written out as one SST
+/// file.
+const FILE_SIZE: usize = 64 << 20;
+
+/// Loader of large amounts of entries into collection `C`, much faster than
+/// writing them one by one.
+///
+/// Created by [`Database::bulk_loader`]. Entries must be added in strictly
+/// increasing key order; they are written to SST files that
+/// [`BulkLoader::finish`] ingests into the collection at once, replacing
+/// existing values. Nothing is visible before then, and nothing is written if
+/// the loader is dropped instead.
+///
+/// Loaded entries bypass the key locks and aren't reported to watchers. The
+/// indexes of `C`, if any, are rebuilt from scratch on finish.
+#[must_use = "a bulk loader does nothing until it is finished"]
+pub struct BulkLoader<'db, C> {
+    db: &'db Database,
+    opts: Options,
+    dir: TempDir,
+    files: Vec<PathBuf>,
+    /// Encoded entries of the next file.
+    pending: Vec<(Vec<u8>, Vec<u8>)>,
+    pending_size: usize,
+    last_key: Option<Vec<u8>>,
+    _marker: PhantomData<C>,
+}
+
+impl Database {
+    pub fn bulk_loader<C: Collection>(&self) -> Result<BulkLoader<'_, C>, Error> {
+        self.cf_handle::<C>()?;
+        let dir = TempDir::new();
+        std::fs::create_dir_all(dir.path())?;
+        let mut opts = Options::default();
+        set_collection_options::<C>(&mut opts);
+        C::cf_options(&mut opts);
+        Ok(BulkLoader {
+            db: self,
+            opts,
+            dir,
+            files: Vec::new(),
+            pending: Vec::new(),
+            pending_size: 0,
+            last_key: None,
+            _marker: PhantomData,
+        })
+    }
+}
+
+impl<'db, C: Collection> BulkLoader<'db, C> {
+    /// Adds an entry, failing with [`Error::UnsortedBulkLoad`] unless `key` is
+    /// greater than the key of the previous one, in the order of
+    /// [`Collection::COMPARATOR`] if set.
+    pub fn add(&mut self, key: impl Into<C::KeyType>, value: &C) -> Result<(), Error> {
+        let key = key.into().serialize().into_owned();
+        let compare = Comparator::of::<C>();
+        if (self.last_key.as_ref()).is_some_and(|last| compare(last, &key) != Ordering::Less) {
+            return Err(Error::UnsortedBulkLoad);
+        }
+        let value = value::encode(&key, value)?;
+        self.pending_size += key.len() + value.len();
+        self.last_key = Some(key.clone());
+        self.pending.push((key, value));
+        if self.pending_size >= FILE_SIZE {
+            self.write_file()?;
+        }
+        Ok(())
+    }
+
+    /// Ingests every added entry into the collection.
+    pub fn finish(mut self) -> Result<(), Error> {
+        self.write_file()?;
+        if !self.files.is_empty() {
+            let mut opts = rocksdb::IngestExternalFileOptions::default();
+            opts.set_move_files(true);
+            self.db.rocksdb.ingest_external_file_cf_opts(
+                &self.db.cf_handle::<C>()?,
+                &opts,
+                std::mem::take(&mut self.files),
+            )?;
+        }
+        if !C::INDEXES.is_empty() {
+            index::rebuild::<C>(self.db)?;
+        }
+        Ok(())
+    }
+
+    fn write_file(&mut self) -> Result<(), Error> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let path = self.dir.path().join(format!("{}.sst", self.files.len()));
+        let mut writer = rocksdb::SstFileWriter::create(&self.opts);
+        writer.open(&path)?;
+        for (key, value) in self.pending.drain(..) {
+            writer.put(key, value)?;
+        }
+        writer.finish()?;
+        self.pending_size = 0;
+        self.files.push(path);
+        Ok(())
+    }
+}
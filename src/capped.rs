@@ -0,0 +1,139 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::{index, iter, Collection, Database, DatabaseBuilder, Error};
+
+/// Share of a cap written between two evictions.
+const TRIM_FRACTION: u64 = 16;
+
+/// Collection holding a bounded number of entries or bytes, like a ring
+/// buffer of log records.
+///
+/// Once a cap is exceeded, entries are evicted from the smallest key on, so
+/// keys should grow with the age of the entries, e.g. sequential ids or
+/// timestamps. Evictions happen after the write that exceeds a cap by a
+/// sixteenth of it, so the collection can briefly hold that much more. They
+/// go around the audit log, watchers and hooks, and don't lock the evicted
+/// keys. The collection has to be registered with
+/// [`DatabaseBuilder::add_capped_collection`].
+pub trait CappedCollection: Collection + 'static {
+    /// Maximum number of entries kept.
+    const MAX_ENTRIES: Option<u64> = None;
+    /// Maximum total size in bytes of the serialized keys and values kept.
+    const MAX_BYTES: Option<u64> = None;
+
+    /// Evicts the oldest entries exceeding the caps now and returns how many
+    /// were evicted.
+    fn trim(db: &Database) -> Result<u64, Error> {
+        trim::<Self>(db)
+    }
+}
+
+impl DatabaseBuilder {
+    /// Adds a collection like [`DatabaseBuilder::add_collection`] and evicts
+    /// its entries exceeding its caps as it is written.
+    pub fn add_capped_collection<T: CappedCollection>(mut self) -> Self {
+        let writes = AtomicU64::new(0);
+        let bytes = AtomicU64::new(0);
+        self.hooks.push_post_commit(
+            T::CF_NAME,
+            Box::new(move |db, key, _old, new| {
+                let size = (key.len() + new.map_or(0, <[u8]>::len)) as u64;
+                let writes_since_trim = writes.fetch_add(1, Ordering::Relaxed) + 1;
+                let bytes_since_trim = bytes.fetch_add(size, Ordering::Relaxed) + size;
+                let due = |cap: Option<u64>, written: u64| {
+                    cap.is_some_and(|cap| written >= (cap / TRIM_FRACTION).max(1))
+                };
+                if due(T::MAX_ENTRIES, writes_since_trim) || due(T::MAX_BYTES, bytes_since_trim) {
+                    writes.store(0, Ordering::Relaxed);
+                    bytes.store(0, Ordering::Relaxed);
+                    T::trim(db)?;
+                }
+                Ok(())
+            }),
+        );
+        self.add_collection::<T>()
+    }
+}
+
+fn trim<T: CappedCollection>(db: &Database) -> Result<u64, Error> {
+    let cf = db.cf_handle::<T>()?;
+    let mut raw = db
+        .rocksdb
+        .raw_iterator_cf_opt(&cf, iter::total_order_read_options());
+    // Walk back from the newest entry to the first one over a cap.
+    raw.seek_to_last();
+    let (mut entries, mut bytes) = (0, 0);
+    let last_evicted = loop {
+        let (key, value) = match (raw.key(), raw.value()) {
+            (Some(key), Some(value)) => (key, value),
+            _ => {
+                raw.status()?;
+                return Ok(0);
+            }
+        };
+        entries += 1;
+        bytes += (key.len() + value.len()) as u64;
+        if T::MAX_ENTRIES.is_some_and(|max| entries > max)
+            || T::MAX_BYTES.is_some_and(|max| bytes > max)
+        {
+            break key.to_vec();
+        }
+        raw.prev();
+    };
+    // Walking on back reaches the other evicted entries, whose index entries
+    // have to be removed as well.
+    let mut batch = rocksdb::WriteBatch::default();
+    let mut claims = index::UniqueClaims::default();
+    let mut first_evicted = last_evicted.clone();
+    let mut evicted = 0;
+    while let (Some(key), Some(value)) = (raw.key(), raw.value()) {
+        if !T::INDEXES.is_empty() {
+            index::reindex::<T>(db, &mut batch, &mut claims, key, Some(value), None)?;
+        }
+        first_evicted = key.to_vec();
+        evicted += 1;
+        raw.prev();
+    }
+    raw.status()?;
+    batch.delete_range_cf(&cf, &first_evicted, &last_evicted);
+    batch.delete_cf(&cf, &last_evicted);
+    db.rocksdb.write(batch)?;
+    Ok(evicted)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize)]
+    struct Log(String);
+
+    impl Collection for Log {
+        type KeyType = u64;
+        const CF_NAME: &'static str = "log";
+    }
+
+    impl CappedCollection for Log {
+        const MAX_ENTRIES: Option<u64> = Some(32);
+    }
+
+    #[test]
+    fn oldest_entries_are_evicted_past_the_cap() {
+        let db = Database::build()
+            .add_capped_collection::<Log>()
+            .open_temp()
+            .unwrap();
+        for id in 0..100u64 {
+            Log::put(id, &Log(id.to_string()), &db).unwrap();
+            assert!(Log::count(&db).unwrap() <= 32 + 32 / TRIM_FRACTION);
+        }
+        assert_eq!(Log::trim(&db).unwrap(), 0);
+        let keys: Vec<u64> = Log::iter(&db)
+            .unwrap()
+            .map(|entry| entry.unwrap().0)
+            .collect();
+        assert_eq!(keys, (68..100).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,84 @@
+use crate::{CfOptions, Database, DatabaseBuilder, Error, Key, Options};
+
+/// Collection of `i64` counters updated with RocksDB merge operands, so
+/// increments never read the counter or take its lock.
+///
+/// Counters that were never incremented, or were reset, read as 0. Sums wrap
+/// around on overflow. The collection has to be registered with
+/// [`DatabaseBuilder::add_counters`].
+pub trait CounterCollection {
+    type KeyType: Key;
+    const CF_NAME: &'static str;
+
+    /// Adds `delta`, which may be negative, to the counter `key`.
+    fn incr<K: Into<Self::KeyType>>(key: K, delta: i64, db: &Database) -> Result<(), Error> {
+        db.rocksdb.merge_cf(
+            &cf_handle(db, Self::CF_NAME)?,
+            key.into().serialize(),
+            delta.to_be_bytes(),
+        )?;
+        Ok(())
+    }
+
+    fn get<K: Into<Self::KeyType>>(key: K, db: &Database) -> Result<i64, Error> {
+        match db
+            .rocksdb
+            .get_pinned_cf(&cf_handle(db, Self::CF_NAME)?, key.into().serialize())?
+        {
+            Some(value) => decode(&value).ok_or(Error::CorruptMetadata),
+            None => Ok(0),
+        }
+    }
+
+    /// Sets the counter `key` back to 0.
+    fn reset<K: Into<Self::KeyType>>(key: K, db: &Database) -> Result<(), Error> {
+        db.rocksdb
+            .delete_cf(&cf_handle(db, Self::CF_NAME)?, key.into().serialize())?;
+        Ok(())
+    }
+}
+
+impl DatabaseBuilder {
+    pub fn add_counters<C: CounterCollection>(mut self) -> Self {
+        self.cf_descriptors.push((
+            C::CF_NAME.to_string(),
+            CfOptions::Default {
+                collection: |_| {},
+                configure: configure_cf::<C>,
+                prefix_bloom: false,
+            },
+        ));
+        self
+    }
+}
+
+fn configure_cf<C: CounterCollection>(opts: &mut Options) {
+    // Sums are associative, so operands can be combined without the value.
+    opts.set_merge_operator(C::CF_NAME, sum, sum);
+}
+
+/// Returning `None` makes RocksDB fail the read or compaction that needed
+/// the merge with a corruption error.
+fn sum(_key: &[u8], existing: Option<&[u8]>, operands: &rocksdb::MergeOperands) -> Option<Vec<u8>> {
+    let mut sum = match existing {
+        Some(existing) => decode(existing)?,
+        None => 0,
+    };
+    for operand in operands {
+        sum = sum.wrapping_add(decode(operand)?);
+    }
+    Some(sum.to_be_bytes().to_vec())
+}
+
+fn decode(bytes: &[u8]) -> Option<i64> {
+    Some(i64::from_be_bytes(bytes.try_into().ok()?))
+}
+
+fn cf_handle<'db>(
+    db: &'db Database,
+    cf_name: &str,
+) -> Result<rocksdb::ColumnFamilyRef<'db>, Error> {
+    db.rocksdb
+        .cf_handle(cf_name)
+        .ok_or(Error::CollectionNotRegistered)
+}
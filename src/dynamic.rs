@@ -0,0 +1,229 @@
+use std::{collections::HashSet, marker::PhantomData};
+
+use crate::{value, CfOptions, Collection, Database, DatabaseBuilder, Error, Iter, Key, Options};
+
+/// Collection of values of type `T` in a column family named at runtime,
+/// such as one per month of date-partitioned data, created by
+/// [`Database::dynamic`].
+///
+/// Values are encoded like those of `T`, but `T`'s indexes, hooks, watchers
+/// and audit log only apply to the collection named [`Collection::CF_NAME`].
+pub struct DynamicCollection<'db, T> {
+    db: &'db Database,
+    name: String,
+    cf: rocksdb::ColumnFamilyRef<'db>,
+    _marker: PhantomData<T>,
+}
+
+/// How the database creates and recognizes dynamic collections.
+pub(crate) struct DynamicSettings {
+    /// Options of the collections created by [`Database::create_dynamic`].
+    pub(crate) opts: Options,
+    /// Prefixes added with [`DatabaseBuilder::add_dynamic_collections`].
+    pub(crate) prefixes: Vec<String>,
+    /// Column families registered on the builder, which aren't dynamic
+    /// collections even if their names start with a prefix.
+    pub(crate) registered: HashSet<String>,
+}
+
+impl DynamicSettings {
+    /// Fails unless `name` can be a dynamic collection.
+    fn check(&self, name: &str) -> Result<(), Error> {
+        let is_dynamic = self
+            .prefixes
+            .iter()
+            .any(|prefix| name.starts_with(prefix.as_str()));
+        if is_dynamic && !self.registered.contains(name) {
+            Ok(())
+        } else {
+            Err(Error::NotDynamic)
+        }
+    }
+}
+
+impl DatabaseBuilder {
+    /// Opens the dynamic collections of the database whose names start with
+    /// `prefix`. Dynamic collections existing in a database have to be
+    /// opened, or opening it fails.
+    pub fn add_dynamic_collections(mut self, prefix: &str) -> Self {
+        self.dynamic_prefixes.push(prefix.to_string());
+        self
+    }
+
+    /// Adds the existing column families matching the dynamic prefixes.
+    pub(crate) fn add_existing_dynamic(&mut self, path: &str) {
+        if self.dynamic_prefixes.is_empty() {
+            return;
+        }
+        // Fails if the database doesn't exist yet, and then there are none.
+        let existing = rocksdb::DB::list_cf(&Options::default(), path).unwrap_or_default();
+        for name in existing {
+            let is_dynamic = self
+                .dynamic_prefixes
+                .iter()
+                .any(|prefix| name.starts_with(prefix.as_str()));
+            let is_added = self.cf_descriptors.iter().any(|(added, _)| *added == name);
+            if is_dynamic && !is_added {
+                self.cf_descriptors.push((
+                    name,
+                    CfOptions::Default {
+                        collection: |_| {},
+                        configure: |_| {},
+                        prefix_bloom: false,
+                    },
+                ));
+            }
+        }
+    }
+}
+
+impl Database {
+    /// Accesses the dynamic collection `name`, which must have been created
+    /// with [`Database::create_dynamic`] and opened with
+    /// [`DatabaseBuilder::add_dynamic_collections`].
+    pub fn dynamic<T: Collection>(&self, name: &str) -> Result<DynamicCollection<'_, T>, Error> {
+        Ok(DynamicCollection {
+            db: self,
+            name: name.to_string(),
+            cf: self
+                .rocksdb
+                .cf_handle(name)
+                .ok_or(Error::CollectionNotRegistered)?,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Creates the dynamic collection `name` with the collection options
+    /// configured on the builder.
+    ///
+    /// Fails with [`Error::NotDynamic`] unless `name` starts with a prefix
+    /// added with [`DatabaseBuilder::add_dynamic_collections`] and isn't
+    /// registered on the builder, so that the collection is opened again
+    /// with the database.
+    pub fn create_dynamic(&self, name: &str) -> Result<(), Error> {
+        self.dynamic.check(name)?;
+        self.rocksdb.create_cf(name, &self.dynamic.opts)?;
+        Ok(())
+    }
+
+    /// Drops the dynamic collection `name`, deleting all its data.
+    ///
+    /// Fails with [`Error::NotDynamic`] for the column families of
+    /// registered collections, which can't be dropped this way. Like with
+    /// [`Database::drop_collection`], [`DynamicCollection`]s of `name`
+    /// already created keep working on the dropped data until they are
+    /// dropped.
+    pub fn drop_dynamic(&self, name: &str) -> Result<(), Error> {
+        self.dynamic.check(name)?;
+        self.rocksdb.drop_cf(name)?;
+        Ok(())
+    }
+}
+
+impl<'db, T: Collection> DynamicCollection<'db, T> {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get(&self, key: impl Into<T::KeyType>) -> Result<Option<T>, Error> {
+        let key: T::KeyType = key.into();
+        let key = key.serialize();
+        self.db
+            .rocksdb
+            .get_pinned_cf(&self.cf, &key)?
+            .map(|value| value::decode_in(&self.name, &key, &value))
+            .transpose()
+    }
+
+    pub fn put(&self, key: impl Into<T::KeyType>, value: &T) -> Result<(), Error> {
+        let key: T::KeyType = key.into();
+        let serialized_key = key.serialize();
+        let value = value::encode_in(&self.name, &serialized_key, value)?;
+        // Serialize with `modify`.
+        let _guard = self.db.locks.lock(&self.name, &serialized_key);
+        self.db.rocksdb.put_cf(&self.cf, &serialized_key, value)?;
+        Ok(())
+    }
+
+    pub fn delete(&self, key: impl Into<T::KeyType>) -> Result<(), Error> {
+        let key: T::KeyType = key.into();
+        let serialized_key = key.serialize();
+        let _guard = self.db.locks.lock(&self.name, &serialized_key);
+        self.db.rocksdb.delete_cf(&self.cf, &serialized_key)?;
+        Ok(())
+    }
+
+    /// Atomically updates the value stored under `key`, see
+    /// [`Collection::modify`].
+    pub fn modify<R>(
+        &self,
+        key: impl Into<T::KeyType>,
+        modifier: impl FnOnce(&mut Option<T>) -> R,
+    ) -> Result<R, Error> {
+        let key: T::KeyType = key.into();
+        let serialized_key = key.serialize();
+        let _guard = self.db.locks.lock(&self.name, &serialized_key);
+        let old_value = self.db.rocksdb.get_pinned_cf(&self.cf, &serialized_key)?;
+        let mut value = old_value
+            .as_deref()
+            .map(|value| value::decode_in(&self.name, &serialized_key, value))
+            .transpose()?;
+        let result = modifier(&mut value);
+        match value {
+            Some(value) => {
+                let value = value::encode_in(&self.name, &serialized_key, &value)?;
+                if old_value.as_deref() != Some(&value[..]) {
+                    self.db.rocksdb.put_cf(&self.cf, &serialized_key, value)?;
+                }
+            }
+            None if old_value.is_some() => self.db.rocksdb.delete_cf(&self.cf, &serialized_key)?,
+            None => {}
+        }
+        Ok(result)
+    }
+
+    /// Iterates over the whole collection in key order.
+    pub fn iter(&self) -> Iter<'db, T> {
+        let mut raw = self.db.rocksdb.raw_iterator_cf(&self.cf);
+        raw.seek_to_first();
+        Iter::new(self.db, raw).in_dynamic(&self.name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Event(String);
+
+    impl Collection for Event {
+        type KeyType = u64;
+        const CF_NAME: &'static str = "events";
+    }
+
+    #[test]
+    fn dynamic_collections_change_while_the_database_is_shared() {
+        let db = Database::build()
+            .add_collection::<Event>()
+            .add_dynamic_collections("events_")
+            .open_temp()
+            .unwrap();
+        let shared = db.clone();
+        db.create_dynamic("events_2024").unwrap();
+        let events = shared.dynamic::<Event>("events_2024").unwrap();
+        events.put(1u64, &Event("a".to_string())).unwrap();
+        assert_eq!(events.get(1u64).unwrap(), Some(Event("a".to_string())));
+        assert!(matches!(
+            db.create_dynamic("events"),
+            Err(Error::NotDynamic)
+        ));
+        db.drop_dynamic("events_2024").unwrap();
+        assert!(matches!(
+            shared.dynamic::<Event>("events_2024"),
+            Err(Error::CollectionNotRegistered)
+        ));
+    }
+}
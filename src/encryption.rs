@@ -0,0 +1,153 @@
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    Aes256Gcm, Nonce,
+};
+
+use crate::Error;
+
+const NONCE_SIZE: usize = 12;
+
+/// Source of the AES-256 keys values are encrypted with.
+///
+/// Keys are identified by a number stored with every value, so that they can
+/// be rotated: new values use the current key, while values written with
+/// older keys stay readable as long as those keys are provided.
+pub trait KeyProvider: Send + Sync {
+    /// Id of the key new values are encrypted with.
+    fn current_key_id(&self) -> u32;
+    /// The key `id`, or `None` if it is not available.
+    fn key(&self, id: u32) -> Option<[u8; 32]>;
+}
+
+/// AES-GCM encryption of the values of a collection, enabled by returning it
+/// from [`Collection::encryption`](crate::Collection::encryption).
+///
+/// Keys are not encrypted, and neither are index entries. Encrypted values
+/// are stored as the key id as a big-endian `u32`, a random nonce and the
+/// ciphertext. The collection and key a value is stored under are
+/// authenticated with it, so a value copied under another key or into
+/// another collection fails to decrypt.
+pub struct Encryption {
+    provider: Box<dyn KeyProvider>,
+}
+
+impl Encryption {
+    pub fn new(provider: impl KeyProvider + 'static) -> Self {
+        Encryption {
+            provider: Box::new(provider),
+        }
+    }
+
+    /// Encrypts the value stored under `key` of the column family `cf_name`.
+    pub(crate) fn encrypt(
+        &self,
+        plaintext: &[u8],
+        cf_name: &str,
+        key: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        let key_id = self.provider.current_key_id();
+        let cipher = self.cipher(key_id)?;
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let aad = associated_data(cf_name, key);
+        let payload = Payload {
+            msg: plaintext,
+            aad: &aad,
+        };
+        let ciphertext = cipher
+            .encrypt(&nonce, payload)
+            .map_err(|_| Error::Encryption)?;
+        let mut bytes = Vec::with_capacity(4 + NONCE_SIZE + ciphertext.len());
+        bytes.extend_from_slice(&key_id.to_be_bytes());
+        bytes.extend_from_slice(nonce.as_slice());
+        bytes.extend_from_slice(&ciphertext);
+        Ok(bytes)
+    }
+
+    /// Decrypts the value stored under `key` of the column family `cf_name`.
+    pub(crate) fn decrypt(
+        &self,
+        bytes: &[u8],
+        cf_name: &str,
+        key: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        if bytes.len() < 4 + NONCE_SIZE {
+            return Err(Error::Encryption);
+        }
+        let (key_id, rest) = bytes.split_at(4);
+        let (nonce, ciphertext) = rest.split_at(NONCE_SIZE);
+        let key_id = u32::from_be_bytes(key_id.try_into().map_err(|_| Error::Encryption)?);
+        let aad = associated_data(cf_name, key);
+        let payload = Payload {
+            msg: ciphertext,
+            aad: &aad,
+        };
+        self.cipher(key_id)?
+            .decrypt(Nonce::from_slice(nonce), payload)
+            .map_err(|_| Error::Encryption)
+    }
+
+    fn cipher(&self, key_id: u32) -> Result<Aes256Gcm, Error> {
+        let key = self.provider.key(key_id).ok_or(Error::Encryption)?;
+        Ok(Aes256Gcm::new(&key.into()))
+    }
+}
+
+/// Where a value is stored: the length of the column family name as a
+/// big-endian `u32`, the name and the key.
+fn associated_data(cf_name: &str, key: &[u8]) -> Vec<u8> {
+    let mut aad = Vec::with_capacity(4 + cf_name.len() + key.len());
+    aad.extend_from_slice(&(cf_name.len() as u32).to_be_bytes());
+    aad.extend_from_slice(cf_name.as_bytes());
+    aad.extend_from_slice(key);
+    aad
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Provides the keys `1..=current`, each filled with its id.
+    struct Keys {
+        current: u32,
+    }
+
+    impl KeyProvider for Keys {
+        fn current_key_id(&self) -> u32 {
+            self.current
+        }
+
+        fn key(&self, id: u32) -> Option<[u8; 32]> {
+            (1..=self.current).contains(&id).then_some([id as u8; 32])
+        }
+    }
+
+    #[test]
+    fn values_decrypt_only_where_they_were_stored() {
+        let encryption = Encryption::new(Keys { current: 1 });
+        let bytes = encryption.encrypt(b"secret", "users", b"a").unwrap();
+        assert_eq!(
+            encryption.decrypt(&bytes, "users", b"a").unwrap(),
+            b"secret"
+        );
+        assert!(encryption.decrypt(&bytes, "users", b"b").is_err());
+        assert!(encryption.decrypt(&bytes, "admins", b"a").is_err());
+        // The name length keeps names and keys from running into each other.
+        assert!(encryption.decrypt(&bytes, "user", b"sa").is_err());
+        assert!(encryption.decrypt(&bytes[..10], "users", b"a").is_err());
+    }
+
+    #[test]
+    fn rotated_keys_keep_old_values_readable() {
+        let old = Encryption::new(Keys { current: 1 }).encrypt(b"old", "cf", b"k");
+        let encryption = Encryption::new(Keys { current: 2 });
+        let new = encryption.encrypt(b"new", "cf", b"k").unwrap();
+        assert_eq!(new[..4], 2u32.to_be_bytes());
+        assert_eq!(
+            encryption.decrypt(&old.unwrap(), "cf", b"k").unwrap(),
+            b"old"
+        );
+        assert_eq!(encryption.decrypt(&new, "cf", b"k").unwrap(), b"new");
+        let without_new_key = Encryption::new(Keys { current: 1 });
+        assert!(without_new_key.decrypt(&new, "cf", b"k").is_err());
+    }
+}
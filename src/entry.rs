@@ -0,0 +1,128 @@
+use std::marker::PhantomData;
+
+use crate::{lock::LockGuard, write, Collection, Database, Error};
+
+/// A single entry of a collection, locked for as long as it is alive.
+///
+/// Every operation is written immediately, and no other write to the key can
+/// interleave with them. Writes of other keys can go on while the entry is
+/// held, but writing its own key other than through the entry deadlocks.
+pub enum Entry<'db, T: Collection> {
+    Occupied(OccupiedEntry<'db, T>),
+    Vacant(VacantEntry<'db, T>),
+}
+
+pub struct OccupiedEntry<'db, T: Collection> {
+    db: &'db Database,
+    key: Vec<u8>,
+    value: T,
+    _guard: LockGuard<'db>,
+}
+
+pub struct VacantEntry<'db, T: Collection> {
+    db: &'db Database,
+    key: Vec<u8>,
+    _guard: LockGuard<'db>,
+    _marker: PhantomData<T>,
+}
+
+impl<'db, T: Collection> Entry<'db, T> {
+    pub(crate) fn new(db: &'db Database, key: Vec<u8>) -> Result<Self, Error> {
+        let guard = db.locks.lock(T::CF_NAME, &key);
+        Ok(match db.get_serialized(&key)? {
+            Some(value) => Entry::Occupied(OccupiedEntry {
+                db,
+                key,
+                value,
+                _guard: guard,
+            }),
+            None => Entry::Vacant(VacantEntry {
+                db,
+                key,
+                _guard: guard,
+                _marker: PhantomData,
+            }),
+        })
+    }
+
+    /// The serialized key of the entry.
+    pub fn key(&self) -> &[u8] {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    /// Returns the stored value, first storing `default` if there is none.
+    pub fn or_insert(self, default: T) -> Result<T, Error> {
+        self.or_insert_with(|| default)
+    }
+
+    /// Returns the stored value, first storing the one built by `default` if
+    /// there is none.
+    pub fn or_insert_with(self, default: impl FnOnce() -> T) -> Result<T, Error> {
+        match self {
+            Entry::Occupied(entry) => Ok(entry.into_value()),
+            Entry::Vacant(entry) => entry.insert(default()),
+        }
+    }
+
+    /// Updates and stores the value if there is one.
+    pub fn and_modify(self, modifier: impl FnOnce(&mut T)) -> Result<Self, Error> {
+        Ok(match self {
+            Entry::Occupied(mut entry) => {
+                modifier(&mut entry.value);
+                entry.write(Some(&entry.value))?;
+                Entry::Occupied(entry)
+            }
+            Entry::Vacant(entry) => Entry::Vacant(entry),
+        })
+    }
+}
+
+impl<'db, T: Collection> OccupiedEntry<'db, T> {
+    pub fn key(&self) -> &[u8] {
+        &self.key
+    }
+
+    pub fn get(&self) -> &T {
+        &self.value
+    }
+
+    pub fn into_value(self) -> T {
+        self.value
+    }
+
+    /// Stores `value`, returning the previous one.
+    pub fn insert(&mut self, value: T) -> Result<T, Error> {
+        self.write(Some(&value))?;
+        Ok(std::mem::replace(&mut self.value, value))
+    }
+
+    /// Deletes the entry, returning its value.
+    pub fn remove(self) -> Result<T, Error> {
+        self.write(None)?;
+        Ok(self.value)
+    }
+
+    fn write(&self, value: Option<&T>) -> Result<(), Error> {
+        let write = match value {
+            Some(value) => write::PendingWrite::put(&self.key, value)?,
+            None => write::PendingWrite::delete::<T>(&self.key),
+        };
+        write::commit(self.db, vec![write])
+    }
+}
+
+impl<'db, T: Collection> VacantEntry<'db, T> {
+    pub fn key(&self) -> &[u8] {
+        &self.key
+    }
+
+    /// Stores `value`, returning it back.
+    pub fn insert(self, value: T) -> Result<T, Error> {
+        let write = write::PendingWrite::put(&self.key, &value)?;
+        write::commit(self.db, vec![write])?;
+        Ok(value)
+    }
+}
@@ -0,0 +1,124 @@
+use std::time::Duration;
+
+use rocksdb::properties;
+
+use crate::{periodic::Periodic, Database, Error};
+
+/// Change in the background work of RocksDB, reported by
+/// [`Database::spawn_event_monitor`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// A flush or compaction failed. Writes may fail until RocksDB recovers
+    /// from the error, which for some errors takes reopening the database.
+    BackgroundError {
+        /// Background errors since the database was opened.
+        total: u64,
+    },
+    /// Flushes of memtables to SST files finished.
+    FlushesFinished { count: u64 },
+    /// Compactions finished.
+    CompactionsFinished { count: u64 },
+    /// Writes started being slowed down, or stopped altogether, because
+    /// flushes or compactions fell behind. Reported again when writes go
+    /// from slowed down to stopped or back.
+    WriteStall {
+        stopped: bool,
+        /// Bytes per second writes are slowed down to, or 0 if they are
+        /// stopped.
+        delayed_write_rate: u64,
+    },
+    /// Writes stopped being slowed down.
+    WriteStallCleared,
+}
+
+/// Monitor of background work, started by [`Database::spawn_event_monitor`].
+/// Dropping it stops the monitor.
+pub struct EventMonitor {
+    _periodic: Periodic,
+}
+
+/// Background work state read from the database properties.
+#[derive(Default)]
+struct State {
+    background_errors: u64,
+    running_flushes: u64,
+    running_compactions: u64,
+    stopped: bool,
+    delayed_write_rate: u64,
+}
+
+impl Database {
+    /// Checks the background work of RocksDB on a background thread every
+    /// `interval`, and passes the changes since the previous check to
+    /// `listen`, until the returned [`EventMonitor`] is dropped or the
+    /// database is closed. Errors reading the state are passed on as well.
+    ///
+    /// The rocksdb crate doesn't expose RocksDB's event listeners, so events
+    /// are derived from properties of the database: flushes and compactions
+    /// that start and finish between two checks go unnoticed. Background
+    /// errors and write stalls last until they are dealt with, so they are
+    /// always reported.
+    pub fn spawn_event_monitor(
+        &self,
+        interval: Duration,
+        mut listen: impl FnMut(Result<Event, Error>) + Send + 'static,
+    ) -> EventMonitor {
+        let mut previous = State::default();
+        EventMonitor {
+            _periodic: Periodic::spawn(self, interval, move |db| match State::read(db) {
+                Ok(state) => {
+                    for event in previous.changes(&state) {
+                        listen(Ok(event));
+                    }
+                    previous = state;
+                }
+                Err(err) => listen(Err(err)),
+            }),
+        }
+    }
+}
+
+impl State {
+    fn read(db: &Database) -> Result<Self, Error> {
+        let property = |name| Ok::<_, Error>(db.rocksdb.property_int_value(name)?.unwrap_or(0));
+        let delayed_write_rate = property(properties::ACTUAL_DELAYED_WRITE_RATE)?;
+        let stopped = property(properties::IS_WRITE_STOPPED)? != 0;
+        Ok(Self {
+            background_errors: property(properties::BACKGROUND_ERRORS)?,
+            running_flushes: property(properties::NUM_RUNNING_FLUSHES)?,
+            running_compactions: property(properties::NUM_RUNNING_COMPACTIONS)?,
+            stopped,
+            delayed_write_rate: if stopped { 0 } else { delayed_write_rate },
+        })
+    }
+
+    fn changes(&self, current: &Self) -> Vec<Event> {
+        let mut events = Vec::new();
+        if current.background_errors > self.background_errors {
+            events.push(Event::BackgroundError {
+                total: current.background_errors,
+            });
+        }
+        if current.running_flushes < self.running_flushes {
+            events.push(Event::FlushesFinished {
+                count: self.running_flushes - current.running_flushes,
+            });
+        }
+        if current.running_compactions < self.running_compactions {
+            events.push(Event::CompactionsFinished {
+                count: self.running_compactions - current.running_compactions,
+            });
+        }
+        let stalled = current.stopped || current.delayed_write_rate != 0;
+        let was_stalled = self.stopped || self.delayed_write_rate != 0;
+        if stalled && (!was_stalled || current.stopped != self.stopped) {
+            events.push(Event::WriteStall {
+                stopped: current.stopped,
+                delayed_write_rate: current.delayed_write_rate,
+            });
+        } else if was_stalled && !stalled {
+            events.push(Event::WriteStallCleared);
+        }
+        events
+    }
+}
@@ -0,0 +1,102 @@
+use std::marker::PhantomData;
+
+use crate::{instrument, value, write, Collection, Database, Error, Iter, Key};
+
+/// Collection `T` of a database with its column family looked up once,
+/// created by [`Database::handle`], for hot paths reading the same collection
+/// over and over.
+///
+/// Its methods behave like those of [`Collection`]. Only reads skip the
+/// lookup: commits resolve the column families of the collection and its
+/// indexes themselves.
+pub struct CollectionHandle<'db, T> {
+    db: &'db Database,
+    cf: rocksdb::ColumnFamilyRef<'db>,
+    _marker: PhantomData<T>,
+}
+
+impl<T> Clone for CollectionHandle<'_, T> {
+    fn clone(&self) -> Self {
+        Self {
+            db: self.db,
+            cf: self.cf.clone(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl Database {
+    pub fn handle<T: Collection>(&self) -> Result<CollectionHandle<'_, T>, Error> {
+        Ok(CollectionHandle {
+            db: self,
+            cf: self.cf_handle::<T>()?,
+            _marker: PhantomData,
+        })
+    }
+}
+
+impl<'db, T: Collection> CollectionHandle<'db, T> {
+    pub fn get(&self, key: impl Into<T::KeyType>) -> Result<Option<T>, Error> {
+        let key: T::KeyType = key.into();
+        let serialized_key = key.serialize();
+        let operation =
+            instrument::Operation::start(self.db.instrument, "get", T::CF_NAME, &serialized_key);
+        let value = self.db.rocksdb.get_pinned_cf(&self.cf, &serialized_key)?;
+        operation.finish(value.as_deref().map(<[u8]>::len));
+        value
+            .map(|value| value::decode(&serialized_key, &value))
+            .transpose()
+    }
+
+    pub fn put(&self, key: impl Into<T::KeyType>, value: &T) -> Result<(), Error> {
+        T::put(key, value, self.db)
+    }
+
+    pub fn delete(&self, key: impl Into<T::KeyType>) -> Result<(), Error> {
+        T::delete(key, self.db)
+    }
+
+    /// Atomically updates the value stored under `key`, see
+    /// [`Collection::modify`].
+    pub fn modify<R>(
+        &self,
+        key: impl Into<T::KeyType>,
+        modifier: impl FnOnce(&mut Option<T>) -> R,
+    ) -> Result<R, Error> {
+        let key: T::KeyType = key.into();
+        let serialized_key = key.serialize();
+        let operation =
+            instrument::Operation::start(self.db.instrument, "modify", T::CF_NAME, &serialized_key);
+        let _guard = self.db.locks.lock(T::CF_NAME, &serialized_key);
+        operation.locked();
+        let old_value = self.db.rocksdb.get_pinned_cf(&self.cf, &serialized_key)?;
+        let mut value = old_value
+            .as_deref()
+            .map(|value| value::decode(&serialized_key, value))
+            .transpose()?;
+        let result = modifier(&mut value);
+        let unchanged = value::matches(
+            T::CF_NAME,
+            &serialized_key,
+            old_value.as_deref(),
+            value.as_ref(),
+        )?;
+        let write = match value {
+            Some(value) => write::PendingWrite::put(&serialized_key, &value)?,
+            None => write::PendingWrite::delete::<T>(&serialized_key),
+        };
+        let value_size = write.value().map(<[u8]>::len);
+        if !unchanged {
+            write::commit(self.db, vec![write])?;
+        }
+        operation.finish(value_size);
+        Ok(result)
+    }
+
+    /// Iterates over the whole collection in key order.
+    pub fn iter(&self) -> Iter<'db, T> {
+        let mut raw = self.db.rocksdb.raw_iterator_cf(&self.cf);
+        raw.seek_to_first();
+        Iter::new(self.db, raw)
+    }
+}
@@ -0,0 +1,10 @@
+//! Stable hashing of stored bytes.
+
+/// 64-bit FNV-1a hash of `bytes`. Unlike [`std::hash::DefaultHasher`], it is
+/// the same across processes and Rust versions, so it can be stored or used
+/// to pick where data lives.
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    })
+}
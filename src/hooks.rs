@@ -0,0 +1,105 @@
+use std::collections::HashMap;
+#[cfg(feature = "search")]
+use std::sync::Arc;
+
+use crate::{value, Collection, Database, DatabaseBuilder, Error, Key};
+
+/// Hook called on a change of a key from its old to its new value, a `None`
+/// value standing for a missing entry.
+pub type Hook<T> = fn(&<T as Collection>::KeyType, Option<&T>, Option<&T>) -> Result<(), Error>;
+
+/// Hook taking the serialized key and values.
+pub(crate) type RawHook =
+    Box<dyn Fn(&Database, &[u8], Option<&[u8]>, Option<&[u8]>) -> Result<(), Error> + Send + Sync>;
+
+/// Hooks registered with [`DatabaseBuilder::on_write`] and
+/// [`DatabaseBuilder::on_commit`] or added by features built on them.
+#[derive(Default)]
+pub(crate) struct Hooks {
+    collections: HashMap<&'static str, CollectionHooks>,
+    /// Search indexes maintained by hooks, keyed by collection name.
+    #[cfg(feature = "search")]
+    pub(crate) search: HashMap<&'static str, Arc<crate::search::SearchIndex>>,
+}
+
+#[derive(Default)]
+pub(crate) struct CollectionHooks {
+    pre_write: Vec<RawHook>,
+    post_commit: Vec<RawHook>,
+}
+
+impl Hooks {
+    pub(crate) fn get(&self, cf_name: &str) -> Option<&CollectionHooks> {
+        self.collections.get(cf_name)
+    }
+
+    pub(crate) fn push_post_commit(&mut self, cf_name: &'static str, hook: RawHook) {
+        let hooks = self.collections.entry(cf_name).or_default();
+        hooks.post_commit.push(hook);
+    }
+}
+
+impl CollectionHooks {
+    pub(crate) fn pre_write(
+        &self,
+        db: &Database,
+        key: &[u8],
+        old: Option<&[u8]>,
+        new: Option<&[u8]>,
+    ) -> Result<(), Error> {
+        self.pre_write
+            .iter()
+            .try_for_each(|hook| hook(db, key, old, new))
+    }
+
+    pub(crate) fn post_commit(
+        &self,
+        db: &Database,
+        key: &[u8],
+        old: Option<&[u8]>,
+        new: Option<&[u8]>,
+    ) -> Result<(), Error> {
+        self.post_commit
+            .iter()
+            .try_for_each(|hook| hook(db, key, old, new))
+    }
+}
+
+fn erase<T: Collection + 'static>(hook: Hook<T>) -> RawHook {
+    Box::new(move |_db, serialized_key, old, new| {
+        let key = T::KeyType::deserialize(serialized_key).ok_or(Error::InvalidKey)?;
+        let old = old
+            .map(|old| value::decode::<T>(serialized_key, old))
+            .transpose()?;
+        let new = new
+            .map(|new| value::decode::<T>(serialized_key, new))
+            .transpose()?;
+        hook(&key, old.as_ref(), new.as_ref())
+    })
+}
+
+impl DatabaseBuilder {
+    /// Calls `hook` before every write of `T` is committed, in the same batch
+    /// as the writes of other collections. An error aborts the whole batch,
+    /// which is then returned by the write.
+    ///
+    /// Hooks run while the written keys are locked, so they must not write
+    /// to the database themselves. Like the audit log, they only see the
+    /// writes that go through collection methods, batches and transactions.
+    pub fn on_write<T: Collection + 'static>(mut self, hook: Hook<T>) -> Self {
+        let hooks = self.hooks.collections.entry(T::CF_NAME).or_default();
+        hooks.pre_write.push(erase(hook));
+        self
+    }
+
+    /// Calls `hook` after every write of `T` was committed, for keeping
+    /// derived data like caches in sync. An error is returned by the write,
+    /// which stays committed, and skips the remaining hooks.
+    ///
+    /// The restrictions of [`DatabaseBuilder::on_write`] apply.
+    pub fn on_commit<T: Collection + 'static>(mut self, hook: Hook<T>) -> Self {
+        let hooks = self.hooks.collections.entry(T::CF_NAME).or_default();
+        hooks.post_commit.push(erase(hook));
+        self
+    }
+}
@@ -0,0 +1,331 @@
+use std::{collections::HashMap, marker::PhantomData, ops::Range};
+
+use crate::{iter, key, value, write, Collection, Database, Error, Key};
+
+/// Name of the column family backing index `index` of collection `cf_name`.
+pub(crate) fn cf_name(cf_name: &str, index: &str) -> String {
+    format!("{}.{}", cf_name, index)
+}
+
+/// Encodes an index value so that it can be followed by the primary key
+/// while preserving the ordering of index values.
+fn encode_value(value: &[u8]) -> Vec<u8> {
+    let mut encoded = Vec::new();
+    key::encode_component(&mut encoded, value);
+    encoded
+}
+
+fn entry_key(value: &[u8], primary_key: &[u8]) -> Vec<u8> {
+    let mut key = encode_value(value);
+    key.extend_from_slice(primary_key);
+    key
+}
+
+/// Splits the primary key off an index entry key.
+fn primary_key(entry_key: &[u8]) -> Option<&[u8]> {
+    let mut i = 0;
+    while i + 1 < entry_key.len() {
+        match entry_key[i..i + 2] {
+            [0, 0] => return Some(&entry_key[i + 2..]),
+            [0, u8::MAX] => i += 2,
+            [0, _] => return None,
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+pub(crate) fn index_cf<'db, T: Collection>(
+    db: &'db Database,
+    index: &str,
+) -> Result<rocksdb::ColumnFamilyRef<'db>, Error> {
+    if !T::INDEXES.contains(&index) {
+        return Err(Error::IndexNotRegistered);
+    }
+    db.rocksdb
+        .cf_handle(&cf_name(T::CF_NAME, index))
+        .ok_or(Error::CollectionNotRegistered)
+}
+
+/// Owners of the unique index values changed by the writes of one batch,
+/// which the index column families don't show until it is written. Keyed by
+/// index column family and encoded index value, `None` marking a value
+/// released by its owner.
+#[derive(Default)]
+pub(crate) struct UniqueClaims(HashMap<(String, Vec<u8>), Option<Vec<u8>>>);
+
+impl UniqueClaims {
+    fn release(&mut self, index_cf_name: String, value: Vec<u8>) {
+        self.0.insert((index_cf_name, value), None);
+    }
+
+    /// Records `key` as the owner of `value`, failing if another record owns
+    /// it.
+    fn claim(
+        &mut self,
+        db: &Database,
+        cf: &rocksdb::ColumnFamilyRef,
+        index_cf_name: String,
+        value: Vec<u8>,
+        key: &[u8],
+    ) -> Result<(), Error> {
+        let claim = (index_cf_name, value);
+        let owned_by_other = match self.0.get(&claim) {
+            Some(owner) => owner.as_deref().is_some_and(|owner| owner != key),
+            None => {
+                let prefix = &claim.1;
+                let mut raw = db
+                    .rocksdb
+                    .raw_iterator_cf_opt(cf, iter::prefix_read_options(prefix, None));
+                raw.seek(prefix);
+                let mut owned_by_other = false;
+                while let Some(entry) = raw.key() {
+                    if &entry[prefix.len()..] != key {
+                        owned_by_other = true;
+                        break;
+                    }
+                    raw.next();
+                }
+                raw.status()?;
+                owned_by_other
+            }
+        };
+        if owned_by_other {
+            return Err(Error::UniqueViolation);
+        }
+        self.0.insert(claim, Some(key.to_vec()));
+        Ok(())
+    }
+}
+
+/// Stages the index entry changes for `key` going from `old` to `new`,
+/// checking the values of unique indexes against `claims`.
+pub(crate) fn reindex<T: Collection>(
+    db: &Database,
+    batch: &mut rocksdb::WriteBatch,
+    claims: &mut UniqueClaims,
+    key: &[u8],
+    old: Option<&[u8]>,
+    new: Option<&[u8]>,
+) -> Result<(), Error> {
+    let index_keys = |value: Option<&[u8]>| -> Result<Vec<(&'static str, Vec<u8>)>, Error> {
+        Ok(match value {
+            Some(value) => value::decode::<T>(key, value)?.index_keys(),
+            None => Vec::new(),
+        })
+    };
+    let old_keys = index_keys(old)?;
+    let new_keys = index_keys(new)?;
+    for entry in &old_keys {
+        if !new_keys.contains(entry) {
+            batch.delete_cf(&index_cf::<T>(db, entry.0)?, entry_key(&entry.1, key));
+            if T::UNIQUE_INDEXES.contains(&entry.0) {
+                claims.release(cf_name(T::CF_NAME, entry.0), encode_value(&entry.1));
+            }
+        }
+    }
+    for entry in &new_keys {
+        if !old_keys.contains(entry) {
+            let cf = index_cf::<T>(db, entry.0)?;
+            if T::UNIQUE_INDEXES.contains(&entry.0) {
+                claims.claim(
+                    db,
+                    &cf,
+                    cf_name(T::CF_NAME, entry.0),
+                    encode_value(&entry.1),
+                    key,
+                )?;
+            }
+            batch.put_cf(&cf, entry_key(&entry.1, key), []);
+        }
+    }
+    Ok(())
+}
+
+/// Iterates over the records of `T` whose index `index` has the given value.
+pub(crate) fn scan<'db, T: Collection>(
+    db: &'db Database,
+    index: &str,
+    value: &[u8],
+) -> Result<IndexIter<'db, T>, Error> {
+    let cf = index_cf::<T>(db, index)?;
+    let prefix = encode_value(value);
+    let mut raw = db
+        .rocksdb
+        .raw_iterator_cf_opt(&cf, iter::prefix_read_options(&prefix, None));
+    raw.seek(&prefix);
+    Ok(IndexIter {
+        db,
+        raw,
+        index: index.to_string(),
+        values: Values::Exact(value.to_vec()),
+        done: false,
+        _marker: PhantomData,
+    })
+}
+
+/// Iterates over the records of `T` whose index `index` has a value in
+/// `values`, in index value order.
+pub(crate) fn scan_range<'db, T: Collection>(
+    db: &'db Database,
+    index: &str,
+    values: Range<Vec<u8>>,
+) -> Result<IndexIter<'db, T>, Error> {
+    let cf = index_cf::<T>(db, index)?;
+    let mut opts = iter::total_order_read_options();
+    opts.set_iterate_upper_bound(encode_value(&values.end));
+    let mut raw = db.rocksdb.raw_iterator_cf_opt(&cf, opts);
+    raw.seek(encode_value(&values.start));
+    Ok(IndexIter {
+        db,
+        raw,
+        index: index.to_string(),
+        values: Values::Range(values),
+        done: false,
+        _marker: PhantomData,
+    })
+}
+
+/// Index values an [`IndexIter`] yields the records of.
+enum Values {
+    Exact(Vec<u8>),
+    Range(Range<Vec<u8>>),
+}
+
+impl Values {
+    fn contains(&self, value: &Vec<u8>) -> bool {
+        match self {
+            Values::Exact(exact) => exact == value,
+            Values::Range(range) => range.contains(value),
+        }
+    }
+}
+
+/// Iterator over the records matching an index lookup, in index value order
+/// and then primary key order.
+///
+/// Yields the decoded primary key together with the decoded record.
+pub struct IndexIter<'db, T> {
+    db: &'db Database,
+    raw: rocksdb::DBRawIterator<'db>,
+    index: String,
+    values: Values,
+    done: bool,
+    _marker: PhantomData<T>,
+}
+
+impl<'db, T: Collection> Iterator for IndexIter<'db, T> {
+    type Item = Result<(T::KeyType, T), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            let primary_key: Box<[u8]> = match self.raw.key() {
+                Some(key) => match primary_key(key) {
+                    Some(primary_key) => Box::from(primary_key),
+                    None => {
+                        self.done = true;
+                        return Some(Err(Error::CorruptMetadata));
+                    }
+                },
+                None => {
+                    self.done = true;
+                    return self.raw.status().err().map(|err| Err(err.into()));
+                }
+            };
+            self.raw.next();
+            let value = match self
+                .db
+                .cf_handle::<T>()
+                .and_then(|cf| Ok(self.db.rocksdb.get_pinned_cf(&cf, &primary_key)?))
+            {
+                Ok(Some(value)) => value,
+                // The record was removed after the index was read.
+                Ok(None) => continue,
+                Err(err) => return Some(Err(err)),
+            };
+            let value: T = match value::decode(&primary_key, &value) {
+                Ok(value) => value,
+                Err(err) => return Some(Err(err)),
+            };
+            // The record was changed after the index was read and no longer
+            // matches.
+            if !value
+                .index_keys()
+                .iter()
+                .any(|(index, key)| *index == self.index && self.values.contains(key))
+            {
+                continue;
+            }
+            return Some(
+                T::KeyType::deserialize(&primary_key)
+                    .map(|key| (key, value))
+                    .ok_or(Error::InvalidKey),
+            );
+        }
+        None
+    }
+}
+
+/// Rebuilds every index of `T` from the stored records.
+pub(crate) fn rebuild<T: Collection>(db: &Database) -> Result<(), Error> {
+    /// Number of records indexed per write batch.
+    const CHUNK_SIZE: usize = 1024;
+
+    let mut batch = rocksdb::WriteBatch::default();
+    for index in T::INDEXES {
+        let cf = index_cf::<T>(db, index)?;
+        write::stage_delete_from(&mut batch, db.rocksdb.raw_iterator_cf(&cf), &cf, None)?;
+    }
+    db.rocksdb.write(batch)?;
+    let mut batch = rocksdb::WriteBatch::default();
+    for (count, entry) in T::iter(db)?.enumerate() {
+        let (key, value) = entry?;
+        for (index, index_value) in value.index_keys() {
+            batch.put_cf(
+                &index_cf::<T>(db, index)?,
+                entry_key(&index_value, &key.serialize()),
+                [],
+            );
+        }
+        if (count + 1) % CHUNK_SIZE == 0 {
+            db.rocksdb.write(std::mem::take(&mut batch))?;
+        }
+    }
+    db.rocksdb.write(batch)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn primary_keys_split_off_entry_keys() {
+        for (value, primary) in [
+            (&b""[..], &b""[..]),
+            (b"email", b"id"),
+            (b"\0a\0", b"\0\0id\0"),
+            (b"\xff\0\xff", b""),
+        ] {
+            assert_eq!(primary_key(&entry_key(value, primary)), Some(primary));
+        }
+    }
+
+    #[test]
+    fn malformed_entry_keys_have_no_primary_key() {
+        assert_eq!(primary_key(b""), None);
+        assert_eq!(primary_key(b"value"), None);
+        assert_eq!(primary_key(b"a\0\x01b"), None);
+    }
+
+    #[test]
+    fn entry_keys_order_by_value_first() {
+        let entries = [
+            entry_key(b"a", b"zz"),
+            entry_key(b"a\0", b"a"),
+            entry_key(b"ab", b""),
+            entry_key(b"b", b""),
+        ];
+        assert!(entries.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+}
@@ -0,0 +1,290 @@
+//! Instrumentation of collection operations, compiled out unless the
+//! `metrics` or `tracing` feature is enabled.
+//!
+//! With `metrics`, every operation records, labelled with its collection:
+//! - `rkyvdb_operations_total`, a counter, labelled with the operation too;
+//! - `rkyvdb_operation_seconds`, a histogram of its latency, labelled the same;
+//! - `rkyvdb_value_bytes`, a histogram of the size of the value read or
+//!   written, if any;
+//! - `rkyvdb_lock_wait_seconds`, a histogram of the time spent waiting for the
+//!   key lock, for operations that take one.
+//!
+//! With `tracing`, operations run in `rkyvdb.operation` debug spans with the
+//! `operation`, `collection`, `key_len` and `value_bytes` fields, iterations in
+//! `rkyvdb.iter` spans counting their `items` and `value_bytes`, and commits
+//! in `rkyvdb.commit` spans with their number of `writes` and batch `bytes`.
+//! Operations and iterations slower than the threshold set with
+//! [`DatabaseBuilder::log_slow_operations`] are logged as warnings, with the
+//! RocksDB perf context counters of their work.
+//!
+//! [`DatabaseBuilder::log_slow_operations`]: crate::DatabaseBuilder::log_slow_operations
+
+#[cfg(any(feature = "metrics", feature = "tracing"))]
+use std::time::Instant;
+#[cfg(feature = "tracing")]
+use std::{cell::Cell, time::Duration};
+
+#[cfg(feature = "tracing")]
+use crate::{perf, DatabaseBuilder};
+
+/// Instrumentation configured on the builder.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct Settings {
+    /// Duration above which operations are logged.
+    #[cfg(feature = "tracing")]
+    pub(crate) slow_threshold: Option<Duration>,
+}
+
+#[cfg(feature = "tracing")]
+impl DatabaseBuilder {
+    /// Logs the gets, modifications, writes and iterations that take longer
+    /// than `threshold` as `tracing` warnings, with their collection, key
+    /// size, duration and the RocksDB perf context counters of their work.
+    /// Measuring the counters slows every operation down a little.
+    pub fn log_slow_operations(mut self, threshold: Duration) -> Self {
+        self.instrument.slow_threshold = Some(threshold);
+        self
+    }
+}
+
+/// One running operation on a collection, recorded once finished.
+pub(crate) struct Operation {
+    #[cfg(feature = "metrics")]
+    name: &'static str,
+    #[cfg(feature = "metrics")]
+    cf_name: &'static str,
+    #[cfg(feature = "metrics")]
+    started: Instant,
+    #[cfg(feature = "tracing")]
+    span: tracing::span::EnteredSpan,
+    #[cfg(feature = "tracing")]
+    _slow: Option<SlowOperation>,
+}
+
+#[cfg_attr(
+    not(all(feature = "metrics", feature = "tracing")),
+    allow(unused_variables)
+)]
+impl Operation {
+    pub(crate) fn start(
+        settings: Settings,
+        name: &'static str,
+        cf_name: &'static str,
+        key: &[u8],
+    ) -> Self {
+        Operation {
+            #[cfg(feature = "metrics")]
+            name,
+            #[cfg(feature = "metrics")]
+            cf_name,
+            #[cfg(feature = "metrics")]
+            started: Instant::now(),
+            #[cfg(feature = "tracing")]
+            span: tracing::debug_span!(
+                "rkyvdb.operation",
+                operation = name,
+                collection = cf_name,
+                key_len = key.len(),
+                value_bytes = tracing::field::Empty,
+            )
+            .entered(),
+            #[cfg(feature = "tracing")]
+            _slow: settings.slow_threshold.map(|threshold| SlowOperation {
+                name,
+                cf_name,
+                key_len: key.len(),
+                threshold,
+                started: Instant::now(),
+                perf: perf::Capture::start(),
+            }),
+        }
+    }
+
+    /// Records the time waited since the start for the key lock, which
+    /// should be taken right after starting.
+    pub(crate) fn locked(&self) {
+        #[cfg(feature = "metrics")]
+        metrics::histogram!(
+            "rkyvdb_lock_wait_seconds",
+            self.started.elapsed(),
+            "collection" => self.cf_name,
+        );
+    }
+
+    /// Records the operation, which read or wrote a value of `value_size`
+    /// bytes.
+    pub(crate) fn finish(self, value_size: Option<usize>) {
+        #[cfg(feature = "tracing")]
+        if let Some(value_size) = value_size {
+            self.span.record("value_bytes", &value_size);
+        }
+        #[cfg(feature = "metrics")]
+        {
+            let labels = [("collection", self.cf_name), ("operation", self.name)];
+            metrics::counter!("rkyvdb_operations_total", 1, &labels);
+            metrics::histogram!("rkyvdb_operation_seconds", self.started.elapsed(), &labels);
+            if let Some(value_size) = value_size {
+                metrics::histogram!(
+                    "rkyvdb_value_bytes",
+                    value_size as f64,
+                    "collection" => self.cf_name,
+                );
+            }
+        }
+    }
+}
+
+/// Operation logged on drop, whether it succeeded or not, if it took longer
+/// than the threshold.
+#[cfg(feature = "tracing")]
+struct SlowOperation {
+    name: &'static str,
+    cf_name: &'static str,
+    key_len: usize,
+    threshold: Duration,
+    started: Instant,
+    perf: perf::Capture,
+}
+
+#[cfg(feature = "tracing")]
+impl Drop for SlowOperation {
+    fn drop(&mut self) {
+        let duration = self.started.elapsed();
+        if duration > self.threshold {
+            tracing::warn!(
+                operation = self.name,
+                collection = self.cf_name,
+                key_len = self.key_len,
+                duration = ?duration,
+                perf = ?self.perf.counters(),
+                "slow rkyvdb operation",
+            );
+        }
+    }
+}
+
+/// Span of a whole iteration, entered while each item is read.
+pub(crate) struct Iteration {
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+    #[cfg(feature = "tracing")]
+    items: Cell<u64>,
+    #[cfg(feature = "tracing")]
+    value_bytes: Cell<u64>,
+    #[cfg(feature = "tracing")]
+    slow: Option<SlowIteration>,
+}
+
+/// Time and perf counters of the reads of an iteration, which may each run
+/// on a different thread, logged on drop if they took longer than the
+/// threshold altogether.
+#[cfg(feature = "tracing")]
+struct SlowIteration {
+    cf_name: &'static str,
+    threshold: Duration,
+    busy: Cell<Duration>,
+    perf: Cell<perf::PerfCounters>,
+}
+
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+impl Iteration {
+    pub(crate) fn start(settings: Settings, cf_name: &'static str) -> Self {
+        Iteration {
+            #[cfg(feature = "tracing")]
+            span: tracing::debug_span!(
+                "rkyvdb.iter",
+                collection = cf_name,
+                items = 0u64,
+                value_bytes = 0u64,
+            ),
+            #[cfg(feature = "tracing")]
+            items: Cell::new(0),
+            #[cfg(feature = "tracing")]
+            value_bytes: Cell::new(0),
+            #[cfg(feature = "tracing")]
+            slow: settings.slow_threshold.map(|threshold| SlowIteration {
+                cf_name,
+                threshold,
+                busy: Cell::new(Duration::ZERO),
+                perf: Cell::new(perf::PerfCounters::default()),
+            }),
+        }
+    }
+
+    #[cfg(feature = "tracing")]
+    pub(crate) fn in_scope<R>(&self, f: impl FnOnce() -> R) -> R {
+        let Some(slow) = &self.slow else {
+            return self.span.in_scope(f);
+        };
+        let started = Instant::now();
+        let capture = perf::Capture::start();
+        let result = self.span.in_scope(f);
+        slow.busy.set(slow.busy.get() + started.elapsed());
+        let mut counters = slow.perf.get();
+        counters.add(&capture.counters());
+        slow.perf.set(counters);
+        result
+    }
+
+    #[cfg(not(feature = "tracing"))]
+    pub(crate) fn in_scope<R>(&self, f: impl FnOnce() -> R) -> R {
+        f()
+    }
+
+    /// Counts one item read, with a value of `value_size` bytes.
+    pub(crate) fn item(&self, value_size: usize) {
+        #[cfg(feature = "tracing")]
+        {
+            self.items.set(self.items.get() + 1);
+            self.value_bytes
+                .set(self.value_bytes.get() + value_size as u64);
+            self.span.record("items", &self.items.get());
+            self.span.record("value_bytes", &self.value_bytes.get());
+        }
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl Drop for Iteration {
+    fn drop(&mut self) {
+        if let Some(slow) = &self.slow {
+            if slow.busy.get() > slow.threshold {
+                tracing::warn!(
+                    operation = "iter",
+                    collection = slow.cf_name,
+                    items = self.items.get(),
+                    duration = ?slow.busy.get(),
+                    perf = ?slow.perf.get(),
+                    "slow rkyvdb operation",
+                );
+            }
+        }
+    }
+}
+
+/// Span of one commit, entered until it is dropped.
+pub(crate) struct Commit {
+    #[cfg(feature = "tracing")]
+    span: tracing::span::EnteredSpan,
+}
+
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+impl Commit {
+    pub(crate) fn start(writes: usize) -> Self {
+        Commit {
+            #[cfg(feature = "tracing")]
+            span: tracing::debug_span!(
+                "rkyvdb.commit",
+                writes = writes,
+                bytes = tracing::field::Empty,
+            )
+            .entered(),
+        }
+    }
+
+    /// Records the size of the batch about to be written.
+    pub(crate) fn batch(&self, bytes: usize) {
+        #[cfg(feature = "tracing")]
+        self.span.record("bytes", &bytes);
+    }
+}
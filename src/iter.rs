@@ -0,0 +1,117 @@
+use std::{borrow::Cow, marker::PhantomData};
+
+use crate::{instrument, value, Collection, Database, Error, Key};
+
+/// Iterator over the entries of a collection, in key order or in reverse key
+/// order.
+///
+/// Yields the decoded key together with the decoded value.
+pub struct Iter<'db, T> {
+    raw: rocksdb::DBRawIterator<'db>,
+    reverse: bool,
+    done: bool,
+    /// Column family the values are decoded as stored in.
+    cf_name: Cow<'static, str>,
+    instrument: instrument::Iteration,
+    _marker: PhantomData<T>,
+}
+
+impl<'db, T: Collection> Iter<'db, T> {
+    /// `raw` must already be positioned on the first entry to yield.
+    pub(crate) fn new(db: &Database, raw: rocksdb::DBRawIterator<'db>) -> Self {
+        Self {
+            raw,
+            reverse: false,
+            done: false,
+            cf_name: Cow::Borrowed(T::CF_NAME),
+            instrument: instrument::Iteration::start(db.instrument, T::CF_NAME),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Like [`Iter::new`], but moves towards smaller keys.
+    pub(crate) fn new_rev(db: &Database, raw: rocksdb::DBRawIterator<'db>) -> Self {
+        Self {
+            reverse: true,
+            ..Self::new(db, raw)
+        }
+    }
+
+    /// Decodes the values as stored in the dynamic collection `cf_name`.
+    pub(crate) fn in_dynamic(mut self, cf_name: &str) -> Self {
+        self.cf_name = Cow::Owned(cf_name.to_string());
+        self
+    }
+}
+
+impl<'db, T: Collection> Iterator for Iter<'db, T> {
+    type Item = Result<(T::KeyType, T), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        self.instrument.in_scope(|| {
+            let (key, value) = match (self.raw.key(), self.raw.value()) {
+                (Some(key), Some(value)) => (key, value),
+                _ => {
+                    self.done = true;
+                    return self.raw.status().err().map(|err| Err(err.into()));
+                }
+            };
+            self.instrument.item(value.len());
+            let item = match T::KeyType::deserialize(key) {
+                Some(deserialized) => {
+                    value::decode_in(&self.cf_name, key, value).map(|value| (deserialized, value))
+                }
+                None => Err(Error::InvalidKey),
+            };
+            if self.reverse {
+                self.raw.prev();
+            } else {
+                self.raw.next();
+            }
+            Some(item)
+        })
+    }
+}
+
+/// Read options for iterators seeking to keys that entries with other
+/// prefixes follow, which mustn't be skipped because of the prefix bloom
+/// filters of a [`Collection::PREFIX_LENGTH`].
+pub(crate) fn total_order_read_options() -> rocksdb::ReadOptions {
+    let mut opts = rocksdb::ReadOptions::default();
+    opts.set_total_order_seek(true);
+    opts
+}
+
+/// Read options limiting iteration to keys starting with `prefix`. The
+/// iterator still has to be seeked to `prefix`. `prefix_length` is the
+/// [`Collection::PREFIX_LENGTH`] of the column family: shorter prefixes span
+/// several extracted ones, so they are seeked in total order.
+pub(crate) fn prefix_read_options(
+    prefix: &[u8],
+    prefix_length: Option<usize>,
+) -> rocksdb::ReadOptions {
+    let mut opts = rocksdb::ReadOptions::default();
+    if prefix_length.is_some_and(|length| prefix.len() < length) {
+        opts.set_total_order_seek(true);
+    }
+    if let Some(upper_bound) = prefix_upper_bound(prefix) {
+        opts.set_iterate_upper_bound(upper_bound);
+    }
+    opts
+}
+
+/// Smallest key that is greater than every key starting with `prefix`, or
+/// `None` if there is no such key (empty prefix or all bytes are `0xff`).
+pub(crate) fn prefix_upper_bound(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut bound = prefix.to_vec();
+    while let Some(last) = bound.pop() {
+        if last != u8::MAX {
+            bound.push(last + 1);
+            return Some(bound);
+        }
+    }
+    None
+}
@@ -0,0 +1,41 @@
+use std::io::{BufRead, Write};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{Collection, Database, Error};
+
+/// Number of imported entries committed per write batch.
+const CHUNK_SIZE: usize = 1024;
+
+pub(crate) fn export<T>(mut writer: impl Write, db: &Database) -> Result<(), Error>
+where
+    T: Collection,
+    T::KeyType: Serialize,
+{
+    for item in T::iter(db)? {
+        serde_json::to_writer(&mut writer, &item?)?;
+        writer.write_all(b"\n").map_err(serde_json::Error::io)?;
+    }
+    writer.flush().map_err(serde_json::Error::io)?;
+    Ok(())
+}
+
+pub(crate) fn import<T>(reader: impl BufRead, db: &Database) -> Result<usize, Error>
+where
+    T: Collection,
+    T::KeyType: DeserializeOwned,
+{
+    let mut imported = 0;
+    let mut batch = db.batch();
+    for item in serde_json::Deserializer::from_reader(reader).into_iter::<(T::KeyType, T)>() {
+        let (key, value) = item?;
+        batch = batch.put(key, &value);
+        imported += 1;
+        if batch.len() == CHUNK_SIZE {
+            batch.commit()?;
+            batch = db.batch();
+        }
+    }
+    batch.commit()?;
+    Ok(imported)
+}
@@ -0,0 +1,296 @@
+use std::{borrow::Cow, cmp::Ordering};
+
+/// Key of a collection.
+///
+/// Collections iterate in the byte order of serialized keys, unless they set
+/// a [`Collection::COMPARATOR`](crate::Collection::COMPARATOR), so the
+/// encoding should preserve the natural order of the key type.
+pub trait Key {
+    fn serialize(&self) -> Cow<'_, [u8]>;
+
+    /// Decodes a serialized key, such as one returned by iteration.
+    fn deserialize(bytes: &[u8]) -> Option<Self>
+    where
+        Self: Sized;
+}
+
+/// Ordering of the serialized keys of a collection, used by RocksDB instead
+/// of their byte order.
+///
+/// The name is stored with the database, which can't be opened again with a
+/// comparator of another name, nor be read correctly with one that orders
+/// keys differently under the same name.
+#[derive(Clone, Copy)]
+pub struct Comparator {
+    pub name: &'static str,
+    pub compare: fn(&[u8], &[u8]) -> Ordering,
+}
+
+impl Comparator {
+    pub(crate) fn of<T: crate::Collection>() -> fn(&[u8], &[u8]) -> Ordering {
+        T::COMPARATOR.map_or(<[u8]>::cmp, |comparator| comparator.compare)
+    }
+}
+
+impl Key for () {
+    fn serialize(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(&[])
+    }
+
+    fn deserialize(bytes: &[u8]) -> Option<Self> {
+        bytes.is_empty().then_some(())
+    }
+}
+
+impl Key for str {
+    fn serialize(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self.as_bytes())
+    }
+}
+
+impl Key for String {
+    fn serialize(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self.as_bytes())
+    }
+
+    fn deserialize(bytes: &[u8]) -> Option<Self> {
+        String::from_utf8(bytes.to_vec()).ok()
+    }
+}
+
+pub struct CaseInsensitiveString(String);
+
+impl CaseInsensitiveString {
+    /// The lowercased string.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&str> for CaseInsensitiveString {
+    fn from(s: &str) -> Self {
+        Self(s.to_lowercase())
+    }
+}
+
+impl From<&String> for CaseInsensitiveString {
+    fn from(s: &String) -> Self {
+        Self(s.to_lowercase())
+    }
+}
+
+impl Key for CaseInsensitiveString {
+    fn serialize(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self.0.as_bytes())
+    }
+
+    fn deserialize(bytes: &[u8]) -> Option<Self> {
+        std::str::from_utf8(bytes).ok().map(Self::from)
+    }
+}
+
+/// Integers are stored big-endian, with the sign bit flipped for signed
+/// types, so that keys iterate in numeric order.
+macro_rules! int_key {
+    ($($int:ty, $sign_bit:expr;)*) => {
+        $(
+            impl Key for $int {
+                fn serialize(&self) -> Cow<'_, [u8]> {
+                    Cow::Owned((self ^ $sign_bit).to_be_bytes().to_vec())
+                }
+
+                fn deserialize(bytes: &[u8]) -> Option<Self> {
+                    Some(<$int>::from_be_bytes(bytes.try_into().ok()?) ^ $sign_bit)
+                }
+            }
+        )*
+    };
+}
+
+int_key! {
+    u32, 0;
+    u64, 0;
+    i32, i32::MIN;
+    i64, i64::MIN;
+}
+
+/// UUID keys are stored as their 16 bytes in big-endian order.
+#[cfg(feature = "uuid")]
+impl Key for uuid::Uuid {
+    fn serialize(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self.as_bytes())
+    }
+
+    fn deserialize(bytes: &[u8]) -> Option<Self> {
+        bytes.try_into().ok().map(uuid::Uuid::from_bytes)
+    }
+}
+
+/// ULID keys are stored as their 16 bytes in big-endian order, so that keys
+/// iterate in creation time order.
+#[cfg(feature = "ulid")]
+impl Key for ulid::Ulid {
+    fn serialize(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(u128::from(*self).to_be_bytes().to_vec())
+    }
+
+    fn deserialize(bytes: &[u8]) -> Option<Self> {
+        Some(ulid::Ulid::from(u128::from_be_bytes(
+            bytes.try_into().ok()?,
+        )))
+    }
+}
+
+/// Key made of several components, ordered component by component.
+///
+/// Each component is the serialized form of a [`Key`], with `0x00` escaped
+/// as `0x00 0xff` and terminated by `0x00 0x00`. This keeps the ordering of
+/// every component, and the encoding of the first components of a key is a
+/// prefix of the encoding of the whole key, so `CompositeKey::from((a,))`
+/// can be used with `iter_prefix` to scan all keys starting with `a`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CompositeKey(Vec<u8>);
+
+impl CompositeKey {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends a component.
+    pub fn push<K: Key + ?Sized>(mut self, component: &K) -> Self {
+        encode_component(&mut self.0, &component.serialize());
+        self
+    }
+
+    /// Splits a serialized composite key, such as one returned by iteration,
+    /// back into the serialized components.
+    pub fn components(bytes: &[u8]) -> Option<Vec<Vec<u8>>> {
+        let mut components = Vec::new();
+        let mut component = Vec::new();
+        let mut bytes = bytes.iter().copied();
+        while let Some(byte) = bytes.next() {
+            if byte != 0 {
+                component.push(byte);
+                continue;
+            }
+            match bytes.next()? {
+                0 => components.push(std::mem::take(&mut component)),
+                u8::MAX => component.push(0),
+                _ => return None,
+            }
+        }
+        component.is_empty().then_some(components)
+    }
+}
+
+impl Key for CompositeKey {
+    fn serialize(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(&self.0)
+    }
+
+    fn deserialize(bytes: &[u8]) -> Option<Self> {
+        Self::components(bytes).map(|_| Self(bytes.to_vec()))
+    }
+}
+
+macro_rules! composite_key_from_tuple {
+    ($($name:ident),*) => {
+        #[allow(non_snake_case)]
+        impl<$($name: Key),*> From<($($name,)*)> for CompositeKey {
+            fn from(($($name,)*): ($($name,)*)) -> Self {
+                Self::new()$(.push(&$name))*
+            }
+        }
+    };
+}
+
+composite_key_from_tuple!(A);
+composite_key_from_tuple!(A, B);
+composite_key_from_tuple!(A, B, C);
+composite_key_from_tuple!(A, B, C, D);
+
+/// Appends `bytes` to `buf`, escaping `0x00` as `0x00 0xff` and terminating
+/// it with `0x00 0x00`, which preserves ordering when more bytes follow.
+pub(crate) fn encode_component(buf: &mut Vec<u8>, bytes: &[u8]) {
+    buf.reserve(bytes.len() + 2);
+    for &byte in bytes {
+        buf.push(byte);
+        if byte == 0 {
+            buf.push(u8::MAX);
+        }
+    }
+    buf.extend_from_slice(&[0, 0]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Asserts that `keys`, in increasing order, serialize in increasing
+    /// byte order and deserialize back.
+    fn assert_ordered<K: Key + PartialEq + std::fmt::Debug>(keys: &[K]) {
+        for pair in keys.windows(2) {
+            assert!(pair[0].serialize() < pair[1].serialize(), "{:?}", pair);
+        }
+        for key in keys {
+            assert_eq!(K::deserialize(&key.serialize()).as_ref(), Some(key));
+        }
+    }
+
+    #[test]
+    fn unsigned_keys_iterate_in_numeric_order() {
+        assert_ordered(&[0u32, 1, 255, 256, u32::MAX]);
+        assert_ordered(&[0u64, 1, 255, 256, u64::MAX]);
+    }
+
+    #[test]
+    fn signed_keys_iterate_in_numeric_order() {
+        assert_ordered(&[i32::MIN, -256, -1, 0, 1, 256, i32::MAX]);
+        assert_ordered(&[i64::MIN, -256, -1, 0, 1, 256, i64::MAX]);
+        assert_eq!(0i64.serialize(), &[0x80, 0, 0, 0, 0, 0, 0, 0][..]);
+    }
+
+    #[test]
+    fn integer_keys_of_the_wrong_length_dont_deserialize() {
+        assert_eq!(u32::deserialize(&[0; 8]), None);
+        assert_eq!(i64::deserialize(&[0; 4]), None);
+    }
+
+    #[test]
+    fn composite_keys_order_component_by_component() {
+        let keys: Vec<CompositeKey> = [
+            ("a", 2u32),
+            ("a", 10),
+            ("a\0", 0),
+            ("a\0\0", 0),
+            ("a\x01", 0),
+            ("ab", 0),
+        ]
+        .into_iter()
+        .map(|(a, b)| CompositeKey::new().push(a).push(&b))
+        .collect();
+        for pair in keys.windows(2) {
+            assert!(pair[0].serialize() < pair[1].serialize(), "{:?}", pair);
+        }
+    }
+
+    #[test]
+    fn composite_keys_split_back_into_their_components() {
+        let key = CompositeKey::from(("a\0b".to_string(), 7u32, String::new()));
+        let components = CompositeKey::components(&key.serialize()).unwrap();
+        assert_eq!(components, [&b"a\0b"[..], &7u32.serialize(), b""]);
+        assert_eq!(CompositeKey::deserialize(&key.serialize()), Some(key));
+        // Unterminated components and unknown escapes are rejected.
+        assert_eq!(CompositeKey::components(b"a\0\0b"), None);
+        assert_eq!(CompositeKey::components(b"a\0\x01\0\0"), None);
+    }
+
+    #[test]
+    fn prefixes_of_composite_keys_are_byte_prefixes() {
+        let prefix = CompositeKey::new().push("a\0");
+        let key = CompositeKey::from(("a\0".to_string(), 1u64));
+        assert!(key.serialize().starts_with(&prefix.serialize()));
+        let other = CompositeKey::new().push("a").push(&1u64);
+        assert!(!other.serialize().starts_with(&prefix.serialize()));
+    }
+}
@@ -1,7 +1,108 @@
-use std::{ops::Deref, sync::Arc};
+#[cfg(feature = "async")]
+mod async_db;
+mod audit;
+mod backup;
+mod batch;
+mod blob;
+mod bulk;
+mod capped;
+mod counter;
+mod dynamic;
+#[cfg(feature = "encryption")]
+mod encryption;
+mod entry;
+mod events;
+mod handle;
+mod hash;
+mod hooks;
+mod index;
+mod instrument;
+mod iter;
+#[cfg(feature = "json")]
+mod json;
+mod key;
+mod lock;
+mod merge;
+mod mergeable;
+mod migrate;
+mod namespace;
+mod page;
+mod perf;
+mod periodic;
+mod pessimistic;
+mod queue;
+mod retry;
+mod scan;
+mod scrub;
+#[cfg(feature = "search")]
+mod search;
+mod sequence;
+mod set;
+mod shard;
+mod snapshot;
+mod soft_delete;
+mod stats;
+mod temp;
+mod time_series;
+mod transaction;
+mod ttl;
+mod value;
+mod versioned;
+mod watch;
+mod write;
 
-pub use rocksdb::Options;
+use std::{
+    collections::HashSet,
+    convert::Infallible,
+    ops::{Deref, Range},
+    sync::{Arc, Mutex},
+};
+
+#[cfg(feature = "async")]
+pub use async_db::{AsyncDatabase, ScanStream};
+pub use audit::{AuditIter, AuditRecord, AuditValue, AuditValues};
+pub use backup::BackupInfo;
+pub use batch::WriteBatch;
+pub use blob::{BlobCollection, BlobReader, BlobWriter};
+pub use bulk::BulkLoader;
+pub use capped::CappedCollection;
+pub use counter::CounterCollection;
+pub use dynamic::DynamicCollection;
+#[cfg(feature = "encryption")]
+pub use encryption::{Encryption, KeyProvider};
+pub use entry::{Entry, OccupiedEntry, VacantEntry};
+pub use events::{Event, EventMonitor};
+pub use handle::CollectionHandle;
+pub use hooks::Hook;
+pub use index::IndexIter;
+pub use iter::Iter;
+pub use key::{CaseInsensitiveString, Comparator, CompositeKey, Key};
+pub use merge::MergeCollection;
+pub use mergeable::Mergeable;
+pub use namespace::{Namespace, NamespaceIter};
+pub use page::{Cursor, Page};
+pub use perf::{with_perf_context, PerfCounters};
+pub use pessimistic::PessimisticTransaction;
+pub use queue::QueueCollection;
+pub use retry::Retry;
+#[cfg(feature = "derive")]
+pub use rkyvdb_derive::Collection;
+pub use rocksdb::{DBCompressionType, Options, ReadOptions};
+pub use scrub::{CorruptEntry, Scrubber};
+#[cfg(feature = "search")]
+pub use search::SearchableCollection;
 use serde::{de::DeserializeOwned, Serialize};
+pub use set::{Members, SetCollection};
+pub use shard::ShardedDatabase;
+pub use snapshot::Snapshot;
+pub use soft_delete::SoftDeleteCollection;
+pub use stats::{CollectionInfo, CollectionProperties, Statistics};
+pub use time_series::{Samples, TimeSeriesCollection};
+pub use transaction::Transaction;
+pub use ttl::ExpiringCollection;
+pub use versioned::{History, VersionedCollection};
+pub use watch::{Change, Watch};
+pub use write::Durability;
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
@@ -13,6 +114,55 @@ pub enum Error {
     RmpDecode(#[from] rmp_serde::decode::Error),
     #[error("RMP encode error")]
     RmpEncode(#[from] rmp_serde::encode::Error),
+    #[error("Index is not declared by the collection")]
+    IndexNotRegistered,
+    #[error("Corrupt rkyvdb metadata")]
+    CorruptMetadata,
+    #[error("Transaction conflicts with a concurrent write")]
+    TransactionConflict,
+    #[error("Stored key can't be decoded as the collection's key type")]
+    InvalidKey,
+    #[error("Collection was written by a newer schema version")]
+    SchemaTooNew,
+    #[error("Bulk loaded keys are not in increasing order")]
+    UnsortedBulkLoad,
+    #[error("Pagination cursor is malformed")]
+    InvalidCursor,
+    #[error("Another record already has the same value of a unique index")]
+    UniqueViolation,
+    #[error("Database was opened as another shard before")]
+    ShardMismatch,
+    #[error("Transaction has no savepoint to roll back to")]
+    NoSavepoint,
+    #[error("Timed out waiting for a lock held by another transaction")]
+    LockTimeout,
+    #[error("Name is not one of a dynamic collection")]
+    NotDynamic,
+    #[error("Argument is out of the range the operation accepts")]
+    InvalidArgument,
+    #[error("I/O error")]
+    Io(#[from] std::io::Error),
+    #[cfg(feature = "encryption")]
+    #[error("Value can't be encrypted or decrypted with the provided keys")]
+    Encryption,
+    #[cfg(feature = "json")]
+    #[error("JSON error")]
+    Json(#[from] serde_json::Error),
+    #[cfg(feature = "search")]
+    #[error("Search index error")]
+    Search(#[from] tantivy::TantivyError),
+    #[cfg(feature = "search")]
+    #[error("Search query can't be parsed")]
+    InvalidQuery,
+}
+
+/// Error of [`Collection::try_modify`].
+#[derive(thiserror::Error, Debug)]
+pub enum ModifyError<E> {
+    #[error("Modifier failed")]
+    Modifier(E),
+    #[error(transparent)]
+    Database(#[from] Error),
 }
 
 #[derive(Clone)]
@@ -28,134 +178,1368 @@ impl Deref for Database {
 
 pub struct DatabaseInner {
     rocksdb: rocksdb::DB,
-    mutex: std::sync::Mutex<()>,
+    locks: lock::LockTable,
+    sequences: sequence::Sequences,
+    watchers: watch::Watchers,
+    schemas: migrate::Schemas,
+    /// Cache configured by [`DatabaseBuilder::block_cache_size`].
+    block_cache: Option<rocksdb::Cache>,
+    audit: Option<audit::AuditLog>,
+    hooks: hooks::Hooks,
+    instrument: instrument::Settings,
+    dynamic: dynamic::DynamicSettings,
+    /// Held while writes to unique indexes are checked and committed.
+    unique_lock: Mutex<()>,
+    /// Directory of a database opened with [`DatabaseBuilder::open_temp`].
+    /// Declared after `rocksdb` so that it is only removed once the database
+    /// is closed.
+    _temp_dir: Option<temp::TempDir>,
+}
+
+impl DatabaseInner {
+    fn cf_handle<T: Collection>(&self) -> Result<rocksdb::ColumnFamilyRef<'_>, Error> {
+        self.rocksdb
+            .cf_handle(T::CF_NAME)
+            .ok_or(Error::CollectionNotRegistered)
+    }
+
+    fn get_serialized<T: Collection>(&self, key: &[u8]) -> Result<Option<T>, Error> {
+        self.rocksdb
+            .get_pinned_cf(&self.cf_handle::<T>()?, key)?
+            .map(|value| value::decode(key, &value))
+            .transpose()
+    }
 }
 
 impl Database {
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        rocksdb: rocksdb::DB,
+        schemas: migrate::Schemas,
+        block_cache: Option<rocksdb::Cache>,
+        audit: Option<AuditValues>,
+        hooks: hooks::Hooks,
+        instrument: instrument::Settings,
+        dynamic: dynamic::DynamicSettings,
+        temp_dir: Option<temp::TempDir>,
+    ) -> Result<Self, Error> {
+        schemas.check(&rocksdb)?;
+        let audit = audit
+            .map(|values| audit::AuditLog::open(&rocksdb, values))
+            .transpose()?;
+        let db = Database(Arc::new(DatabaseInner {
+            rocksdb,
+            locks: lock::LockTable::new(),
+            sequences: sequence::Sequences::default(),
+            watchers: watch::Watchers::default(),
+            schemas,
+            block_cache,
+            audit,
+            hooks,
+            instrument,
+            dynamic,
+            unique_lock: Mutex::new(()),
+            _temp_dir: temp_dir,
+        }));
+        #[cfg(feature = "search")]
+        search::fill_all(&db)?;
+        Ok(db)
+    }
+
     pub fn build() -> DatabaseBuilder {
         let mut opts = Options::default();
         opts.create_if_missing(true);
         opts.create_missing_column_families(true);
-        opts.set_compression_type(rocksdb::DBCompressionType::Zstd);
+        opts.set_compression_type(DBCompressionType::Zstd);
+        let mut cf_opts = Options::default();
+        cf_opts.set_compression_type(DBCompressionType::Zstd);
         DatabaseBuilder {
             opts,
+            cf_opts,
+            block_cache_size: None,
+            audit: None,
+            hooks: hooks::Hooks::default(),
+            instrument: instrument::Settings::default(),
+            dynamic_prefixes: vec![],
             cf_descriptors: vec![],
+            schemas: migrate::Schemas::default(),
+        }
+    }
+
+    /// Opens the database at `path` with default options, creating it and
+    /// the column families of all collections in `C` as needed.
+    ///
+    /// `C` is a tuple of collections, e.g. `(User, Session)`.
+    pub fn open_with_collections<C: Collections>(path: &str) -> Result<Database, Error> {
+        Self::build().add_collections::<C>().open(path)
+    }
+
+    /// Opens a database with the collections in `C` in a new temporary
+    /// directory, like [`DatabaseBuilder::open_temp`].
+    pub fn new_temp<C: Collections>() -> Result<Database, Error> {
+        Self::build().add_collections::<C>().open_temp()
+    }
+
+    /// Deletes the database at `path` with all its files. Fails if the
+    /// database is still open.
+    pub fn destroy(path: &str) -> Result<(), Error> {
+        rocksdb::DB::destroy(&Options::default(), path)?;
+        Ok(())
+    }
+
+    /// Tries to recover the database at `path` after a corruption, keeping
+    /// as much data as can be read. Entries in damaged files may be lost, and
+    /// deleted ones may reappear. Writes to collections that were still only
+    /// in the write-ahead log are dropped, so flush before closing a database
+    /// that may need repairing. The database must not be open.
+    pub fn repair(path: &str) -> Result<(), Error> {
+        rocksdb::DB::repair(&Options::default(), path)?;
+        Ok(())
+    }
+
+    /// Applies the writes made by the primary since the last catch-up to a
+    /// database opened with [`DatabaseBuilder::open_as_secondary`].
+    pub fn try_catch_up(&self) -> Result<(), Error> {
+        self.rocksdb.try_catch_up_with_primary()?;
+        Ok(())
+    }
+
+    /// Bytes currently held by the block cache configured with
+    /// [`DatabaseBuilder::block_cache_size`], or `None` if there is none.
+    pub fn block_cache_usage(&self) -> Option<usize> {
+        self.block_cache.as_ref().map(rocksdb::Cache::get_usage)
+    }
+
+    /// Flushes the memtables of every column family to SST files, so that
+    /// everything written so far no longer depends on the WAL.
+    pub fn flush(&self) -> Result<(), Error> {
+        for cf_name in rocksdb::DB::list_cf(&Options::default(), self.rocksdb.path())? {
+            if let Some(cf) = self.rocksdb.cf_handle(&cf_name) {
+                self.rocksdb.flush_cf(&cf)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Drops the column families of collection `C` and of its indexes,
+    /// deleting all their data. They are created again, empty, the next time
+    /// the database is opened with `C` registered.
+    ///
+    /// Other handles to the database can go on using it: reads and writes of
+    /// `C` fail with [`Error::CollectionNotRegistered`] from then on, and
+    /// iterators and [`CollectionHandle`]s already created keep working on
+    /// the dropped data until they are dropped.
+    pub fn drop_collection<C: Collection>(&self) -> Result<(), Error> {
+        self.cf_handle::<C>()?;
+        for index in C::INDEXES {
+            self.rocksdb.drop_cf(&index::cf_name(C::CF_NAME, index))?;
         }
+        self.rocksdb.drop_cf(C::CF_NAME)?;
+        Ok(())
     }
 }
 
 #[derive(Default)]
 pub struct DatabaseBuilder {
     opts: Options,
-    cf_descriptors: Vec<rocksdb::ColumnFamilyDescriptor>,
+    /// Options for collections added without explicit options.
+    cf_opts: Options,
+    block_cache_size: Option<usize>,
+    audit: Option<AuditValues>,
+    hooks: hooks::Hooks,
+    instrument: instrument::Settings,
+    /// Name prefixes of the dynamic collections to open.
+    dynamic_prefixes: Vec<String>,
+    cf_descriptors: Vec<(String, CfOptions)>,
+    schemas: migrate::Schemas,
+}
+
+/// Database options, column families and shared block cache to open with,
+/// and the options of dynamic collections created once open.
+type Descriptors = (
+    Options,
+    Vec<rocksdb::ColumnFamilyDescriptor>,
+    Option<rocksdb::Cache>,
+    dynamic::DynamicSettings,
+);
+
+enum CfOptions {
+    /// `cf_opts` with the options declared by the collection, adjusted by
+    /// `configure`.
+    Default {
+        collection: fn(&mut Options),
+        configure: fn(&mut Options),
+        /// Whether SST files get prefix bloom filters.
+        prefix_bloom: bool,
+    },
+    Explicit(Options),
+}
+
+/// Applies [`Collection::COMPRESSION`], [`Collection::ZSTD_DICTIONARY_SIZE`],
+/// [`Collection::COMPARATOR`], [`Collection::WRITE_SOFT_LIMIT`] and the prefix
+/// extractor of [`Collection::PREFIX_LENGTH`].
+fn set_collection_options<T: Collection>(opts: &mut Options) {
+    if let Some(compression) = T::COMPRESSION {
+        opts.set_compression_type(compression);
+    }
+    if T::ZSTD_DICTIONARY_SIZE != 0 {
+        let size = T::ZSTD_DICTIONARY_SIZE as i32;
+        // Default window bits, level and strategy.
+        opts.set_compression_options(-14, 32767, 0, size);
+        // Samples of about 100 times the dictionary size, as advised by zstd.
+        opts.set_zstd_max_train_bytes(size.saturating_mul(100));
+    }
+    if let Some(comparator) = T::COMPARATOR {
+        opts.set_comparator(comparator.name, comparator.compare);
+    }
+    if let Some(limit) = T::WRITE_SOFT_LIMIT {
+        opts.set_soft_pending_compaction_bytes_limit(limit);
+    }
+    if let Some(length) = T::PREFIX_LENGTH {
+        opts.set_prefix_extractor(rocksdb::SliceTransform::create_fixed_prefix(length));
+        opts.set_memtable_prefix_bloom_ratio(0.1);
+    }
 }
 
 impl DatabaseBuilder {
-    pub fn add_collection<T: Collection>(mut self) -> Self {
-        self.cf_descriptors
-            .push(rocksdb::ColumnFamilyDescriptor::new(
-                T::CF_NAME.to_string(),
-                Options::default(),
-            ));
-        self
+    pub fn add_collection<T: Collection>(self) -> Self {
+        self.add_collection_with::<T>(T::cf_options)
     }
+    /// Adds a collection whose column family options are the builder defaults
+    /// adjusted by `configure`.
+    pub(crate) fn add_collection_with<T: Collection>(
+        mut self,
+        configure: fn(&mut Options),
+    ) -> Self {
+        self.cf_descriptors.push((
+            T::CF_NAME.to_string(),
+            CfOptions::Default {
+                collection: set_collection_options::<T>,
+                configure,
+                prefix_bloom: T::PREFIX_LENGTH.is_some(),
+            },
+        ));
+        self.register::<T>()
+    }
+    /// Adds a collection with `opts` as its column family options, instead of
+    /// the builder defaults adjusted by [`Collection::cf_options`].
     pub fn add_collection_opt<T: Collection>(mut self, opts: Options) -> Self {
         self.cf_descriptors
-            .push(rocksdb::ColumnFamilyDescriptor::new(
-                T::CF_NAME.to_string(),
-                opts,
+            .push((T::CF_NAME.to_string(), CfOptions::Explicit(opts)));
+        self.register::<T>()
+    }
+    /// Adds every collection of the tuple `C` with default options.
+    pub fn add_collections<C: Collections>(self) -> Self {
+        C::add_to(self)
+    }
+    /// Records the schema of `T` and adds the column families of its indexes.
+    fn register<T: Collection>(mut self) -> Self {
+        self.schemas.register::<T>();
+        for index in T::INDEXES {
+            self.cf_descriptors.push((
+                index::cf_name(T::CF_NAME, index),
+                CfOptions::Default {
+                    collection: |_| {},
+                    configure: |_| {},
+                    prefix_bloom: false,
+                },
             ));
+        }
         self
     }
+    /// Replaces the database-wide options. Collection options are not affected.
     pub fn set_options(mut self, opts: Options) -> Self {
         self.opts = opts;
         self
     }
-    pub fn open(self, path: &str) -> Result<Database, rocksdb::Error> {
-        let db = rocksdb::DB::open_cf_descriptors(&self.opts, path, self.cf_descriptors)?;
-        Ok(Database(Arc::new(DatabaseInner {
-            rocksdb: db,
-            mutex: std::sync::Mutex::new(()),
-        })))
+    pub fn create_if_missing(mut self, create_if_missing: bool) -> Self {
+        self.opts.create_if_missing(create_if_missing);
+        self
+    }
+    /// Compression used by the database and by collections added without
+    /// explicit options. Defaults to zstd.
+    pub fn compression(mut self, compression: DBCompressionType) -> Self {
+        self.opts.set_compression_type(compression);
+        self.cf_opts.set_compression_type(compression);
+        self
+    }
+    /// Size in bytes of an LRU block cache shared by the database, by the
+    /// indexes and by collections added without explicit options, bounding
+    /// the memory used for cached blocks however many collections there are.
+    /// Its usage is reported by [`Database::block_cache_usage`].
+    pub fn block_cache_size(mut self, size: usize) -> Self {
+        self.block_cache_size = Some(size);
+        self
+    }
+    /// Size in bytes of each memtable of collections added without explicit
+    /// options.
+    pub fn write_buffer_size(mut self, size: usize) -> Self {
+        self.opts.set_write_buffer_size(size);
+        self.cf_opts.set_write_buffer_size(size);
+        self
+    }
+    /// Sizes the background flush and compaction thread pools for
+    /// `parallelism` cores.
+    pub fn increase_parallelism(mut self, parallelism: i32) -> Self {
+        self.opts.increase_parallelism(parallelism);
+        self
+    }
+    pub fn max_background_jobs(mut self, jobs: i32) -> Self {
+        self.opts.set_max_background_jobs(jobs);
+        self
+    }
+    /// Limits the rate at which flushes and compactions write to disk to
+    /// `bytes_per_sec`, so that bulk writes leave disk bandwidth to reads.
+    /// Up to `burst` bytes can be written at once, after which writers wait
+    /// for the limit to catch up.
+    pub fn rate_limit(mut self, bytes_per_sec: u64, burst: u64) -> Self {
+        let bytes_per_sec = bytes_per_sec.max(1);
+        // RocksDB hands out one refill period's worth of bytes per refill.
+        let refill_period_us = (u128::from(burst) * 1_000_000 / u128::from(bytes_per_sec)).max(1);
+        self.opts.set_ratelimiter(
+            i64::try_from(bytes_per_sec).unwrap_or(i64::MAX),
+            i64::try_from(refill_period_us).unwrap_or(i64::MAX),
+            // RocksDB's default fairness between high and low priority IO.
+            10,
+        );
+        self
+    }
+    /// Maximum number of files kept open, or -1 for no limit.
+    pub fn max_open_files(mut self, max_open_files: i32) -> Self {
+        self.opts.set_max_open_files(max_open_files);
+        self
+    }
+    pub fn open(mut self, path: &str) -> Result<Database, Error> {
+        let schemas = std::mem::take(&mut self.schemas);
+        let audit = self.audit;
+        let hooks = std::mem::take(&mut self.hooks);
+        let instrument = self.instrument;
+        let (opts, cf_descriptors, block_cache, dynamic) = self.into_descriptors(Some(path))?;
+        let rocksdb = rocksdb::DB::open_cf_descriptors(&opts, path, cf_descriptors)?;
+        schemas.stamp_empty(&rocksdb)?;
+        Database::new(
+            rocksdb,
+            schemas,
+            block_cache,
+            audit,
+            hooks,
+            instrument,
+            dynamic,
+            None,
+        )
+    }
+    /// Opens a new database in a unique temporary directory, which is
+    /// deleted once the last handle to the database is dropped. Meant for
+    /// tests.
+    pub fn open_temp(mut self) -> Result<Database, Error> {
+        let temp_dir = temp::TempDir::new();
+        let schemas = std::mem::take(&mut self.schemas);
+        let audit = self.audit;
+        let hooks = std::mem::take(&mut self.hooks);
+        let instrument = self.instrument;
+        let (opts, cf_descriptors, block_cache, dynamic) = self.into_descriptors(None)?;
+        let rocksdb = rocksdb::DB::open_cf_descriptors(&opts, temp_dir.path(), cf_descriptors)?;
+        schemas.stamp_empty(&rocksdb)?;
+        Database::new(
+            rocksdb,
+            schemas,
+            block_cache,
+            audit,
+            hooks,
+            instrument,
+            dynamic,
+            Some(temp_dir),
+        )
+    }
+    /// Opens the database at `path` without write access. Collections that
+    /// don't exist yet can't be created, and every write fails.
+    pub fn open_read_only(mut self, path: &str) -> Result<Database, Error> {
+        let schemas = std::mem::take(&mut self.schemas);
+        let audit = self.audit;
+        let hooks = std::mem::take(&mut self.hooks);
+        let instrument = self.instrument;
+        let (opts, cf_descriptors, block_cache, dynamic) = self.into_descriptors(Some(path))?;
+        let rocksdb =
+            rocksdb::DB::open_cf_descriptors_read_only(&opts, path, cf_descriptors, false)?;
+        Database::new(
+            rocksdb,
+            schemas,
+            block_cache,
+            audit,
+            hooks,
+            instrument,
+            dynamic,
+            None,
+        )
+    }
+    /// Opens a read-only replica of the database at `primary_path`, which
+    /// may still be open for writing by another process. The replica keeps
+    /// its own logs in `secondary_path` and only sees the writes of the
+    /// primary once [`Database::try_catch_up`] is called.
+    pub fn open_as_secondary(
+        mut self,
+        primary_path: &str,
+        secondary_path: &str,
+    ) -> Result<Database, Error> {
+        // Secondaries have to keep every file of the primary open.
+        self.opts.set_max_open_files(-1);
+        let schemas = std::mem::take(&mut self.schemas);
+        let audit = self.audit;
+        let hooks = std::mem::take(&mut self.hooks);
+        let instrument = self.instrument;
+        let (opts, cf_descriptors, block_cache, dynamic) =
+            self.into_descriptors(Some(primary_path))?;
+        let rocksdb = rocksdb::DB::open_cf_descriptors_as_secondary(
+            &opts,
+            primary_path,
+            secondary_path,
+            cf_descriptors,
+        )?;
+        Database::new(
+            rocksdb,
+            schemas,
+            block_cache,
+            audit,
+            hooks,
+            instrument,
+            dynamic,
+            None,
+        )
+    }
+    /// `path` is where the database may already exist, if anywhere.
+    fn into_descriptors(mut self, path: Option<&str>) -> Result<Descriptors, rocksdb::Error> {
+        let mut registered: HashSet<String> = self
+            .cf_descriptors
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect();
+        registered.insert(rocksdb::DEFAULT_COLUMN_FAMILY_NAME.to_string());
+        if let Some(path) = path {
+            self.add_existing_dynamic(path);
+        }
+        let block_cache = self
+            .block_cache_size
+            .map(rocksdb::Cache::new_lru_cache)
+            .transpose()?;
+        if let Some(cache) = &block_cache {
+            let mut table_opts = rocksdb::BlockBasedOptions::default();
+            table_opts.set_block_cache(cache);
+            self.opts.set_block_based_table_factory(&table_opts);
+            self.cf_opts.set_block_based_table_factory(&table_opts);
+        }
+        let cf_opts = self.cf_opts;
+        let cf_descriptors = self
+            .cf_descriptors
+            .into_iter()
+            .map(|(name, opts)| {
+                let opts = match opts {
+                    CfOptions::Default {
+                        collection,
+                        configure,
+                        prefix_bloom,
+                    } => {
+                        let mut opts = cf_opts.clone();
+                        if prefix_bloom {
+                            let mut table_opts = rocksdb::BlockBasedOptions::default();
+                            if let Some(cache) = &block_cache {
+                                table_opts.set_block_cache(cache);
+                            }
+                            table_opts.set_bloom_filter(10.0, false);
+                            // Point lookups check the prefix of the key instead,
+                            // and full filters holding whole keys too make prefix
+                            // seeks miss keys.
+                            table_opts.set_whole_key_filtering(false);
+                            opts.set_block_based_table_factory(&table_opts);
+                        }
+                        collection(&mut opts);
+                        configure(&mut opts);
+                        opts
+                    }
+                    CfOptions::Explicit(opts) => opts,
+                };
+                rocksdb::ColumnFamilyDescriptor::new(name, opts)
+            })
+            .collect();
+        let dynamic = dynamic::DynamicSettings {
+            opts: cf_opts,
+            prefixes: self.dynamic_prefixes,
+            registered,
+        };
+        Ok((self.opts, cf_descriptors, block_cache, dynamic))
     }
 }
 
-pub trait Key {
-    fn serialize(&self) -> &[u8];
+/// A set of collections registered together, implemented for tuples of up to
+/// eight collections.
+pub trait Collections {
+    fn add_to(builder: DatabaseBuilder) -> DatabaseBuilder;
 }
 
-impl Key for () {
-    fn serialize(&self) -> &[u8] {
-        &[]
-    }
+macro_rules! impl_collections {
+    ($($name:ident),*) => {
+        impl<$($name: Collection),*> Collections for ($($name,)*) {
+            fn add_to(builder: DatabaseBuilder) -> DatabaseBuilder {
+                builder$(.add_collection::<$name>())*
+            }
+        }
+    };
 }
 
-impl Key for str {
-    fn serialize(&self) -> &[u8] {
-        self.as_bytes()
-    }
-}
+impl_collections!();
+impl_collections!(A);
+impl_collections!(A, B);
+impl_collections!(A, B, C);
+impl_collections!(A, B, C, D);
+impl_collections!(A, B, C, D, E);
+impl_collections!(A, B, C, D, E, F);
+impl_collections!(A, B, C, D, E, F, G);
+impl_collections!(A, B, C, D, E, F, G, H);
 
-pub struct CaseInsensitiveString(String);
+pub trait Collection: Serialize + DeserializeOwned + Sized {
+    type KeyType: Key;
+    const CF_NAME: &'static str;
+    /// Version of the encoding of the values, to be bumped whenever it
+    /// changes incompatibly; see [`DatabaseBuilder::add_migration`].
+    const SCHEMA_VERSION: u32 = 0;
+    /// Version stamped on every value written, for collections that upgrade
+    /// old values lazily with [`Collection::upgrade`] instead of migrating
+    /// them all at once. Values of version 0 carry no stamp.
+    const VERSION: u32 = 0;
+    /// Compression of the collection, instead of the one configured on the
+    /// [`DatabaseBuilder`]. [`DBCompressionType::Lz4`] needs the `lz4`
+    /// feature.
+    const COMPRESSION: Option<DBCompressionType> = None;
+    /// Maximum size in bytes of the zstd dictionaries, or 0 to compress
+    /// without one. RocksDB trains a dictionary on samples of the values of
+    /// every SST file it writes and stores it in the file, which makes small
+    /// values that share structure compress far better. Only used with zstd.
+    const ZSTD_DICTIONARY_SIZE: u32 = 0;
+    /// Length in bytes of the key prefixes that [`Collection::iter_prefix`]
+    /// is called with, if they all have the same. RocksDB then keeps bloom
+    /// filters of these prefixes, so that seeking to a prefix skips the files
+    /// that don't contain it. Suits composite keys whose first component has a
+    /// fixed size, like a `u64` id.
+    const PREFIX_LENGTH: Option<usize> = None;
+    /// Ordering of the keys, instead of the byte order of their serialized
+    /// form, for collections iterating in a domain order like version
+    /// numbers. Prefix iteration and deletion still take keys starting with
+    /// the same bytes to be adjacent, and ranges must have a start that
+    /// doesn't order after their end.
+    const COMPARATOR: Option<Comparator> = None;
+    /// Bytes of compaction the collection may fall behind on before RocksDB
+    /// slows writes down, instead of its default of 64 GiB. A low limit on a
+    /// bulk imported collection makes the import back off before the pending
+    /// compactions slow reads down. The slowdown applies to writes to every
+    /// collection, not only this one.
+    const WRITE_SOFT_LIMIT: Option<usize> = None;
+    /// Names of the secondary indexes maintained for this collection.
+    const INDEXES: &'static [&'static str] = &[];
+    /// Names of the [`Collection::INDEXES`] whose values may belong to one
+    /// record at most. Writes giving a record a value another record already
+    /// has fail with [`Error::UniqueViolation`]. Writes of one batch are
+    /// checked in order, so a value has to be released before it is taken.
+    ///
+    /// Rebuilding an index and bulk loads don't check the constraint.
+    const UNIQUE_INDEXES: &'static [&'static str] = &[];
 
-impl From<&str> for CaseInsensitiveString {
-    fn from(s: &str) -> Self {
-        Self(s.to_lowercase())
-    }
-}
+    /// Adjusts the options of this collection's column family, such as
+    /// compression, bloom filters or write buffer size. `opts` starts out as
+    /// the defaults configured on the [`DatabaseBuilder`].
+    fn cf_options(_opts: &mut Options) {}
 
-impl From<&String> for CaseInsensitiveString {
-    fn from(s: &String) -> Self {
-        Self(s.to_lowercase())
+    /// Decodes a value stored by an older [`Collection::VERSION`]. Upgraded
+    /// values are written back with the current version the next time they
+    /// are modified.
+    ///
+    /// Decodes `old` as the current type by default, which suits additions
+    /// of fields with `#[serde(default)]`.
+    fn upgrade(old: &[u8], _version: u32) -> Result<Self, Error> {
+        Ok(rmp_serde::decode::from_slice(old)?)
     }
-}
 
-impl<'a> Key for CaseInsensitiveString {
-    fn serialize(&self) -> &[u8] {
-        self.0.as_bytes()
+    /// Encryption of the values of this collection, if any. Typically a
+    /// `static` initialized on startup with the key provider.
+    ///
+    /// Turning it on or off for a collection that already has values makes
+    /// them unreadable, as does losing a key.
+    #[cfg(feature = "encryption")]
+    fn encryption() -> Option<&'static Encryption> {
+        None
     }
-}
 
-pub trait Collection: Serialize + DeserializeOwned + Sized {
-    type KeyType: Key;
-    const CF_NAME: &'static str;
+    /// Index entries for this value, as `(index name, index value)` pairs.
+    /// Every name must be listed in [`Collection::INDEXES`].
+    fn index_keys(&self) -> Vec<(&'static str, Vec<u8>)> {
+        Vec::new()
+    }
 
+    /// Reads and decodes the value stored under `key`.
+    ///
+    /// Values are always fully decoded and validated, so a corrupt or foreign
+    /// value is reported as [`Error::RmpDecode`] rather than trusted.
     fn get<K: Into<Self::KeyType>>(key: K, db: &Database) -> Result<Option<Self>, Error> {
-        let key = key.into();
-        let cf = db
+        Self::get_opt(key, &ReadOptions::default(), db)
+    }
+
+    /// Like [`Collection::get`], with `opts` for the read, e.g. to leave the
+    /// block cache alone with `fill_cache(false)`.
+    fn get_opt<K: Into<Self::KeyType>>(
+        key: K,
+        opts: &ReadOptions,
+        db: &Database,
+    ) -> Result<Option<Self>, Error> {
+        let key: Self::KeyType = key.into();
+        let serialized_key = key.serialize();
+        let operation =
+            instrument::Operation::start(db.instrument, "get", Self::CF_NAME, &serialized_key);
+        let value =
+            db.rocksdb
+                .get_pinned_cf_opt(&db.cf_handle::<Self>()?, &serialized_key, opts)?;
+        operation.finish(value.as_deref().map(<[u8]>::len));
+        value
+            .map(|value| value::decode(&serialized_key, &value))
+            .transpose()
+    }
+
+    /// Returns what `project` takes from the value stored under `key`, such as
+    /// one field, dropping the rest of the value right away. The value is
+    /// still decoded in full: MessagePack values can't be read in part.
+    fn get_field<K: Into<Self::KeyType>, F>(
+        key: K,
+        project: impl FnOnce(&Self) -> F,
+        db: &Database,
+    ) -> Result<Option<F>, Error> {
+        let key: Self::KeyType = key.into();
+        let serialized_key = key.serialize();
+        let operation =
+            instrument::Operation::start(db.instrument, "get", Self::CF_NAME, &serialized_key);
+        let value = db
             .rocksdb
-            .cf_handle(Self::CF_NAME)
-            .ok_or(Error::CollectionNotRegistered)?;
-        db.rocksdb.get_pinned_cf(cf, key.serialize())?.map_or(Ok(None), |value| {
-            rmp_serde::decode::from_slice(&value).map_err(Error::RmpDecode)
+            .get_pinned_cf(&db.cf_handle::<Self>()?, &serialized_key)?;
+        operation.finish(value.as_deref().map(<[u8]>::len));
+        value
+            .map(|value| Ok(project(&value::decode(&serialized_key, &value)?)))
+            .transpose()
+    }
+
+    /// Returns whether a value is stored under `key`, without decoding it.
+    /// Bloom filters and memtables usually rule out missing keys without a
+    /// read; other keys are read to confirm.
+    fn exists<K: Into<Self::KeyType>>(key: K, db: &Database) -> Result<bool, Error> {
+        let key: Self::KeyType = key.into();
+        let serialized_key = key.serialize();
+        let operation =
+            instrument::Operation::start(db.instrument, "exists", Self::CF_NAME, &serialized_key);
+        let cf = db.cf_handle::<Self>()?;
+        let exists = db.rocksdb.key_may_exist_cf(&cf, &serialized_key)
+            && db.rocksdb.get_pinned_cf(&cf, &serialized_key)?.is_some();
+        operation.finish(None);
+        Ok(exists)
+    }
+
+    /// Reads the values stored under all `keys` in a single batched lookup.
+    /// Values are returned in the order of `keys`.
+    fn get_many<K: Into<Self::KeyType>>(
+        keys: impl IntoIterator<Item = K>,
+        db: &Database,
+    ) -> Result<Vec<Option<Self>>, Error> {
+        let cf = db.cf_handle::<Self>()?;
+        let keys: Vec<Self::KeyType> = keys.into_iter().map(Into::into).collect();
+        let serialized_keys: Vec<_> = keys.iter().map(Key::serialize).collect();
+        db.rocksdb
+            .multi_get_cf(serialized_keys.iter().map(|key| (&cf, key)))
+            .into_iter()
+            .zip(&serialized_keys)
+            .map(|(value, key)| value?.map(|value| value::decode(key, &value)).transpose())
+            .collect()
+    }
+
+    /// Atomically updates the value stored under `key`.
+    ///
+    /// `modifier` gets the current value, or `None` if there is none, and
+    /// leaves the value to store in it; setting it to `None` deletes the
+    /// entry. Whatever `modifier` returns is passed back to the caller.
+    fn modify<K: Into<Self::KeyType>, R>(
+        key: K,
+        db: &Database,
+        modifier: impl FnOnce(&mut Option<Self>) -> R,
+    ) -> Result<R, Error> {
+        Self::modify_opt(key, Durability::Default, db, modifier)
+    }
+
+    /// Like [`Collection::modify`], committing the write with `durability`.
+    fn modify_opt<K: Into<Self::KeyType>, R>(
+        key: K,
+        durability: Durability,
+        db: &Database,
+        modifier: impl FnOnce(&mut Option<Self>) -> R,
+    ) -> Result<R, Error> {
+        Self::try_modify_opt(key, durability, db, |value| {
+            Ok::<_, Infallible>(modifier(value))
+        })
+        .map_err(|err| match err {
+            ModifyError::Modifier(never) => match never {},
+            ModifyError::Database(err) => err,
         })
     }
 
-    fn modify<K: Into<Self::KeyType>>(
+    /// Like [`Collection::modify`], but `modifier` can fail. Its error is
+    /// returned as [`ModifyError::Modifier`] and nothing is written, whatever
+    /// it did to the value.
+    ///
+    /// Nothing is written either when `modifier` leaves the value as it was,
+    /// compared the way [`Collection::compare_and_swap`] compares values, so
+    /// modifiers that only sometimes change the value are cheap.
+    fn try_modify<K: Into<Self::KeyType>, R, E>(
         key: K,
         db: &Database,
-        modifier: impl FnOnce(Option<Self>) -> Option<Self>,
-    ) -> Result<(), Error> {
-        let cf = db
+        modifier: impl FnOnce(&mut Option<Self>) -> Result<R, E>,
+    ) -> Result<R, ModifyError<E>> {
+        Self::try_modify_opt(key, Durability::Default, db, modifier)
+    }
+
+    /// Like [`Collection::try_modify`], committing the write with
+    /// `durability`.
+    fn try_modify_opt<K: Into<Self::KeyType>, R, E>(
+        key: K,
+        durability: Durability,
+        db: &Database,
+        modifier: impl FnOnce(&mut Option<Self>) -> Result<R, E>,
+    ) -> Result<R, ModifyError<E>> {
+        let key: Self::KeyType = key.into();
+        let serialized_key = key.serialize();
+        let operation =
+            instrument::Operation::start(db.instrument, "modify", Self::CF_NAME, &serialized_key);
+        let _guard = db.locks.lock(Self::CF_NAME, &serialized_key);
+        operation.locked();
+        let old_value = db
+            .rocksdb
+            .get_pinned_cf(&db.cf_handle::<Self>()?, &serialized_key)
+            .map_err(Error::from)?;
+        let mut value = match &old_value {
+            Some(old_value) => Some(value::decode(&serialized_key, old_value)?),
+            None => None,
+        };
+        let result = modifier(&mut value).map_err(ModifyError::Modifier)?;
+        let unchanged = value::matches(
+            Self::CF_NAME,
+            &serialized_key,
+            old_value.as_deref(),
+            value.as_ref(),
+        )?;
+        let write = match value {
+            Some(value) => write::PendingWrite::put(&serialized_key, &value)?,
+            None => write::PendingWrite::delete::<Self>(&serialized_key),
+        };
+        let value_size = write.value().map(<[u8]>::len);
+        if !unchanged {
+            write::commit_opt(db, vec![write], durability)?;
+        }
+        operation.finish(value_size);
+        Ok(result)
+    }
+
+    /// Like [`Collection::modify`], but runs `modifier` without holding the
+    /// lock of `key`, so a slow modifier doesn't hold up other writers. The
+    /// value is only written if it didn't change while `modifier` ran;
+    /// otherwise it is read again and `modifier` rerun, as often as `retry`
+    /// allows, before failing with [`Error::TransactionConflict`].
+    ///
+    /// `modifier` may run several times, so it shouldn't have side effects.
+    fn modify_optimistic<K: Into<Self::KeyType>, R>(
+        key: K,
+        retry: Retry,
+        db: &Database,
+        mut modifier: impl FnMut(&mut Option<Self>) -> R,
+    ) -> Result<R, Error> {
+        let key: Self::KeyType = key.into();
+        let serialized_key = key.serialize();
+        let cf = db.cf_handle::<Self>()?;
+        let mut attempt = 0;
+        loop {
+            let old_value = db.rocksdb.get_cf(&cf, &serialized_key)?;
+            let mut value = old_value
+                .as_deref()
+                .map(|value| value::decode(&serialized_key, value))
+                .transpose()?;
+            let result = modifier(&mut value);
+            if value::matches(
+                Self::CF_NAME,
+                &serialized_key,
+                old_value.as_deref(),
+                value.as_ref(),
+            )? {
+                return Ok(result);
+            }
+            let write = match value {
+                Some(value) => write::PendingWrite::put(&serialized_key, &value)?,
+                None => write::PendingWrite::delete::<Self>(&serialized_key),
+            };
+            {
+                let _guard = db.locks.lock(Self::CF_NAME, &serialized_key);
+                let current = db.rocksdb.get_pinned_cf(&cf, &serialized_key)?;
+                if current.as_deref() == old_value.as_deref() {
+                    write::commit(db, vec![write])?;
+                    return Ok(result);
+                }
+            }
+            if !retry.wait(attempt) {
+                return Err(Error::TransactionConflict);
+            }
+            attempt += 1;
+        }
+    }
+
+    /// Atomically updates the values stored under all `keys`, like
+    /// [`Collection::modify`] does for one key: `modifier` gets the current
+    /// values in the order of `keys` and leaves the values to store in them.
+    /// Every changed value is written in one batch, so no reader sees only
+    /// part of the update.
+    ///
+    /// A key given twice gets its current value in both places, and the
+    /// value left in the last one is stored.
+    fn modify_many<K: Into<Self::KeyType>, R>(
+        keys: impl IntoIterator<Item = K>,
+        db: &Database,
+        modifier: impl FnOnce(&mut [Option<Self>]) -> R,
+    ) -> Result<R, Error> {
+        let cf = db.cf_handle::<Self>()?;
+        let keys: Vec<Self::KeyType> = keys.into_iter().map(Into::into).collect();
+        let serialized_keys: Vec<_> = keys.iter().map(Key::serialize).collect();
+        let _guard = db
+            .locks
+            .lock_many(serialized_keys.iter().map(|key| (Self::CF_NAME, &**key)));
+        let old_values = db
             .rocksdb
-            .cf_handle(Self::CF_NAME)
-            .ok_or(Error::CollectionNotRegistered)?;
+            .multi_get_cf(serialized_keys.iter().map(|key| (&cf, key)))
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut values = old_values
+            .iter()
+            .zip(&serialized_keys)
+            .map(|(value, key)| {
+                value
+                    .as_deref()
+                    .map(|value| value::decode(key, value))
+                    .transpose()
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let result = modifier(&mut values);
+        let mut writes = Vec::new();
+        let mut written = HashSet::new();
+        let entries = serialized_keys.iter().zip(values).zip(&old_values);
+        for ((key, value), old_value) in entries.rev() {
+            if !written.insert(key) {
+                continue;
+            }
+            if value::matches(Self::CF_NAME, key, old_value.as_deref(), value.as_ref())? {
+                continue;
+            }
+            writes.push(match value {
+                Some(value) => write::PendingWrite::put(key, &value)?,
+                None => write::PendingWrite::delete::<Self>(key),
+            });
+        }
+        if !writes.is_empty() {
+            write::commit(db, writes)?;
+        }
+        Ok(result)
+    }
+
+    /// Replaces the value stored under `key` with `new` (deleting it if `new`
+    /// is `None`), but only if the current value is `expected`. Returns
+    /// whether the value was replaced.
+    ///
+    /// Values are compared in their serialized form, a current value of an
+    /// older [`Collection::VERSION`] after upgrading it, so types whose
+    /// encoding isn't deterministic, like those containing a `HashMap`, may
+    /// compare unequal to an identical value.
+    fn compare_and_swap<K: Into<Self::KeyType>>(
+        key: K,
+        expected: Option<&Self>,
+        new: Option<&Self>,
+        db: &Database,
+    ) -> Result<bool, Error> {
+        let key: Self::KeyType = key.into();
+        let serialized_key = key.serialize();
+        let write = match new {
+            Some(value) => write::PendingWrite::put(&serialized_key, value)?,
+            None => write::PendingWrite::delete::<Self>(&serialized_key),
+        };
+        let _guard = db.locks.lock(Self::CF_NAME, &serialized_key);
+        let current = db
+            .rocksdb
+            .get_pinned_cf(&db.cf_handle::<Self>()?, &serialized_key)?;
+        if !value::matches(Self::CF_NAME, &serialized_key, current.as_deref(), expected)? {
+            return Ok(false);
+        }
+        write::commit(db, vec![write])?;
+        Ok(true)
+    }
+
+    /// Returns the value stored under `key`, first storing the one built by
+    /// `default` if there is none.
+    fn get_or_insert_with<K: Into<Self::KeyType>>(
+        key: K,
+        default: impl FnOnce() -> Self,
+        db: &Database,
+    ) -> Result<Self, Error> {
+        let key: Self::KeyType = key.into();
+        let serialized_key = key.serialize();
+        let _guard = db.locks.lock(Self::CF_NAME, &serialized_key);
+        if let Some(value) = db.get_serialized(&serialized_key)? {
+            return Ok(value);
+        }
+        let value = default();
+        let write = write::PendingWrite::put(&serialized_key, &value)?;
+        write::commit(db, vec![write])?;
+        Ok(value)
+    }
+
+    /// Locks the entry stored under `key` for a sequence of operations, see
+    /// [`Entry`].
+    fn entry<K: Into<Self::KeyType>>(key: K, db: &Database) -> Result<Entry<'_, Self>, Error> {
+        let key: Self::KeyType = key.into();
+        Entry::new(db, key.serialize().into_owned())
+    }
+
+    /// Stores `value` under `key`, replacing any previous value without
+    /// reading it.
+    fn put<K: Into<Self::KeyType>>(key: K, value: &Self, db: &Database) -> Result<(), Error> {
+        Self::put_opt(key, value, Durability::Default, db)
+    }
+
+    /// Like [`Collection::put`], committing the write with `durability`.
+    fn put_opt<K: Into<Self::KeyType>>(
+        key: K,
+        value: &Self,
+        durability: Durability,
+        db: &Database,
+    ) -> Result<(), Error> {
         let key: Self::KeyType = key.into();
         let serialized_key = key.serialize();
-        let _guard = db.mutex.lock().unwrap();
-        let old_value = db.rocksdb.get_pinned_cf(cf, serialized_key)?.map_or(Ok(None), |value| {
-            rmp_serde::decode::from_slice(&value).map_err(Error::RmpDecode)
-        })?;
-        let value = modifier(old_value);
-        if let Some(value) = value {
-            db.rocksdb.put_cf(
-                cf,
-                serialized_key,
-                rmp_serde::encode::to_vec(&value).map_err(Error::RmpEncode)?,
+        let write = write::PendingWrite::put(&serialized_key, value)?;
+        let value_size = write.value().map(<[u8]>::len);
+        let operation =
+            instrument::Operation::start(db.instrument, "put", Self::CF_NAME, &serialized_key);
+        let _guard = db.locks.lock(Self::CF_NAME, &serialized_key);
+        operation.locked();
+        write::commit_opt(db, vec![write], durability)?;
+        operation.finish(value_size);
+        Ok(())
+    }
+
+    /// Stores `value` under `key` and returns the value it replaced.
+    fn insert<K: Into<Self::KeyType>>(
+        key: K,
+        value: &Self,
+        db: &Database,
+    ) -> Result<Option<Self>, Error> {
+        let key: Self::KeyType = key.into();
+        let serialized_key = key.serialize();
+        let write = write::PendingWrite::put(&serialized_key, value)?;
+        let _guard = db.locks.lock(Self::CF_NAME, &serialized_key);
+        let old_value = db.get_serialized(&serialized_key)?;
+        write::commit(db, vec![write])?;
+        Ok(old_value)
+    }
+
+    /// Removes the value stored under `key` and returns it.
+    fn remove<K: Into<Self::KeyType>>(key: K, db: &Database) -> Result<Option<Self>, Error> {
+        let key: Self::KeyType = key.into();
+        let serialized_key = key.serialize();
+        let _guard = db.locks.lock(Self::CF_NAME, &serialized_key);
+        let old_value = db.get_serialized(&serialized_key)?;
+        if old_value.is_some() {
+            write::commit(
+                db,
+                vec![write::PendingWrite::delete::<Self>(&serialized_key)],
             )?;
-        } else {
-            db.rocksdb.delete_cf(cf, serialized_key)?;
         }
+        Ok(old_value)
+    }
+
+    /// Removes the value stored under `key`, if any.
+    fn delete<K: Into<Self::KeyType>>(key: K, db: &Database) -> Result<(), Error> {
+        let key: Self::KeyType = key.into();
+        let serialized_key = key.serialize();
+        let write = write::PendingWrite::delete::<Self>(&serialized_key);
+        let operation =
+            instrument::Operation::start(db.instrument, "delete", Self::CF_NAME, &serialized_key);
+        let _guard = db.locks.lock(Self::CF_NAME, &serialized_key);
+        operation.locked();
+        write::commit(db, vec![write])?;
+        operation.finish(None);
         Ok(())
     }
+
+    /// Moves the value stored under `old` to `new`, replacing any value
+    /// stored under `new`, in one atomic write. Returns whether there was a
+    /// value to move.
+    fn rename_key<K: Into<Self::KeyType>>(old: K, new: K, db: &Database) -> Result<bool, Error> {
+        let (old, new): (Self::KeyType, Self::KeyType) = (old.into(), new.into());
+        let (old, new) = (old.serialize(), new.serialize());
+        let _guard = db
+            .locks
+            .lock_many([(Self::CF_NAME, &*old), (Self::CF_NAME, &*new)]);
+        let Some(value) = db.get_serialized::<Self>(&old)? else {
+            return Ok(false);
+        };
+        if old != new {
+            // Deleted first to release its unique index values.
+            let writes = vec![
+                write::PendingWrite::delete::<Self>(&old),
+                write::PendingWrite::put(&new, &value)?,
+            ];
+            write::commit(db, writes)?;
+        }
+        Ok(true)
+    }
+
+    /// Moves the value stored under `key` to the same key of collection `T`,
+    /// converted with [`Into`], in one atomic write. Returns whether there
+    /// was a value to move.
+    fn move_to<T>(key: impl Into<Self::KeyType>, db: &Database) -> Result<bool, Error>
+    where
+        T: Collection<KeyType = Self::KeyType>,
+        Self: Into<T>,
+    {
+        let key: Self::KeyType = key.into();
+        let serialized_key = key.serialize();
+        let _guard = db.locks.lock_many([
+            (Self::CF_NAME, &*serialized_key),
+            (T::CF_NAME, &*serialized_key),
+        ]);
+        let Some(value) = db.get_serialized::<Self>(&serialized_key)? else {
+            return Ok(false);
+        };
+        let writes = vec![
+            write::PendingWrite::delete::<Self>(&serialized_key),
+            write::PendingWrite::put::<T>(&serialized_key, &value.into())?,
+        ];
+        write::commit(db, writes)?;
+        Ok(true)
+    }
+
+    /// Deletes all entries with keys in `range.start..range.end`.
+    ///
+    /// Uses a RocksDB range deletion, unless the collection has indexes: those
+    /// are maintained by deleting the entries one by one in a single batch.
+    fn delete_range<K: Into<Self::KeyType>>(range: Range<K>, db: &Database) -> Result<(), Error> {
+        let start: Self::KeyType = range.start.into();
+        let end: Self::KeyType = range.end.into();
+        write::delete_range::<Self>(db, &start.serialize(), Some(&end.serialize()))
+    }
+
+    /// Deletes all entries whose key starts with `prefix`, like
+    /// [`Collection::delete_range`].
+    fn delete_prefix<K: Into<Self::KeyType>>(prefix: K, db: &Database) -> Result<(), Error> {
+        let prefix: Self::KeyType = prefix.into();
+        let prefix = prefix.serialize();
+        let end = iter::prefix_upper_bound(&prefix);
+        write::delete_range::<Self>(db, &prefix, end.as_deref())
+    }
+
+    /// Deletes every entry of the collection, keeping its column family.
+    fn truncate(db: &Database) -> Result<(), Error> {
+        write::truncate::<Self>(db)
+    }
+
+    /// Flushes the memtables of the collection and of its indexes to SST
+    /// files.
+    fn flush(db: &Database) -> Result<(), Error> {
+        db.rocksdb.flush_cf(&db.cf_handle::<Self>()?)?;
+        for index in Self::INDEXES {
+            db.rocksdb.flush_cf(&index::index_cf::<Self>(db, index)?)?;
+        }
+        Ok(())
+    }
+
+    /// Compacts the entries with keys in `range.start..range.end`, reclaiming
+    /// the space of values deleted or overwritten there. Useful after large
+    /// deletions; blocks until the compaction is done.
+    fn compact_range<K: Into<Self::KeyType>>(range: Range<K>, db: &Database) -> Result<(), Error> {
+        let start: Self::KeyType = range.start.into();
+        let end: Self::KeyType = range.end.into();
+        db.rocksdb.compact_range_cf(
+            &db.cf_handle::<Self>()?,
+            Some(start.serialize()),
+            Some(end.serialize()),
+        );
+        Ok(())
+    }
+
+    /// Watches the changes committed to the entry `key` from now on.
+    fn watch<K: Into<Self::KeyType>>(key: K, db: &Database) -> Watch<Self> {
+        let key: Self::KeyType = key.into();
+        db.watchers.subscribe(key.serialize().into_owned(), true)
+    }
+
+    /// Watches the changes committed from now on to entries whose key starts
+    /// with `prefix`.
+    fn watch_prefix<K: Into<Self::KeyType>>(prefix: K, db: &Database) -> Watch<Self> {
+        let prefix: Self::KeyType = prefix.into();
+        db.watchers
+            .subscribe(prefix.serialize().into_owned(), false)
+    }
+
+    /// Iterates over the whole collection in key order.
+    fn iter(db: &Database) -> Result<Iter<'_, Self>, Error> {
+        let mut raw = db.rocksdb.raw_iterator_cf(&db.cf_handle::<Self>()?);
+        raw.seek_to_first();
+        Ok(Iter::new(db, raw))
+    }
+
+    /// Like [`Collection::iter`], with `opts` for the iterator: batch jobs
+    /// typically turn off `fill_cache` and raise `readahead_size`, and
+    /// iterate bounds limit the keys it visits.
+    fn iter_opt(mut opts: ReadOptions, db: &Database) -> Result<Iter<'_, Self>, Error> {
+        opts.set_total_order_seek(true);
+        let mut raw = db
+            .rocksdb
+            .raw_iterator_cf_opt(&db.cf_handle::<Self>()?, opts);
+        raw.seek_to_first();
+        Ok(Iter::new(db, raw))
+    }
+
+    /// Calls `f` on every entry of the collection, scanning disjoint key
+    /// ranges in parallel on rayon's global thread pool. Entries are visited in no
+    /// particular order, all from a consistent snapshot; the scan stops at
+    /// the first error.
+    fn par_scan(db: &Database, f: impl Fn(Self::KeyType, Self) + Sync) -> Result<(), Error> {
+        scan::par_scan::<Self>(db, &f)
+    }
+
+    /// Iterates over the whole collection in reverse key order.
+    fn iter_rev(db: &Database) -> Result<Iter<'_, Self>, Error> {
+        let mut raw = db.rocksdb.raw_iterator_cf(&db.cf_handle::<Self>()?);
+        raw.seek_to_last();
+        Ok(Iter::new_rev(db, raw))
+    }
+
+    /// Returns the entry with the smallest key, if any.
+    fn first(db: &Database) -> Result<Option<(Self::KeyType, Self)>, Error> {
+        Self::iter(db)?.next().transpose()
+    }
+
+    /// Returns the entry with the largest key, if any.
+    fn last(db: &Database) -> Result<Option<(Self::KeyType, Self)>, Error> {
+        Self::iter_rev(db)?.next().transpose()
+    }
+
+    /// Returns the entry with the smallest key starting with `prefix`, if any.
+    fn first_with_prefix<K: Into<Self::KeyType>>(
+        prefix: K,
+        db: &Database,
+    ) -> Result<Option<(Self::KeyType, Self)>, Error> {
+        Self::iter_prefix(prefix, db)?.next().transpose()
+    }
+
+    /// Returns the entry with the largest key starting with `prefix`, if any.
+    fn last_with_prefix<K: Into<Self::KeyType>>(
+        prefix: K,
+        db: &Database,
+    ) -> Result<Option<(Self::KeyType, Self)>, Error> {
+        let prefix: Self::KeyType = prefix.into();
+        let prefix = prefix.serialize();
+        let mut opts = iter::total_order_read_options();
+        if let Some(upper_bound) = iter::prefix_upper_bound(&prefix) {
+            opts.set_iterate_upper_bound(upper_bound);
+        }
+        opts.set_iterate_lower_bound(prefix.into_owned());
+        let mut raw = db
+            .rocksdb
+            .raw_iterator_cf_opt(&db.cf_handle::<Self>()?, opts);
+        raw.seek_to_last();
+        Iter::new_rev(db, raw).next().transpose()
+    }
+
+    /// Iterates in key order over the entries with keys greater than or equal
+    /// to `key`.
+    fn seek<K: Into<Self::KeyType>>(key: K, db: &Database) -> Result<Iter<'_, Self>, Error> {
+        let key: Self::KeyType = key.into();
+        let mut raw = db
+            .rocksdb
+            .raw_iterator_cf_opt(&db.cf_handle::<Self>()?, iter::total_order_read_options());
+        raw.seek(key.serialize());
+        Ok(Iter::new(db, raw))
+    }
+
+    /// Iterates in reverse key order over the entries with keys less than or
+    /// equal to `key`.
+    fn seek_for_prev<K: Into<Self::KeyType>>(
+        key: K,
+        db: &Database,
+    ) -> Result<Iter<'_, Self>, Error> {
+        let key: Self::KeyType = key.into();
+        let mut raw = db
+            .rocksdb
+            .raw_iterator_cf_opt(&db.cf_handle::<Self>()?, iter::total_order_read_options());
+        raw.seek_for_prev(key.serialize());
+        Ok(Iter::new_rev(db, raw))
+    }
+
+    /// Iterates over all entries whose key starts with `prefix`.
+    fn iter_prefix<K: Into<Self::KeyType>>(
+        prefix: K,
+        db: &Database,
+    ) -> Result<Iter<'_, Self>, Error> {
+        let cf = db.cf_handle::<Self>()?;
+        let prefix: Self::KeyType = prefix.into();
+        let prefix = prefix.serialize();
+        let mut raw = db
+            .rocksdb
+            .raw_iterator_cf_opt(&cf, iter::prefix_read_options(&prefix, Self::PREFIX_LENGTH));
+        raw.seek(prefix);
+        Ok(Iter::new(db, raw))
+    }
+
+    /// Returns up to `limit` entries with keys greater than `after`, or the
+    /// first entries if `after` is `None`, with a cursor to get the next page
+    /// from. `limit` should be positive: an empty page has no cursor.
+    fn page(
+        after: Option<Self::KeyType>,
+        limit: usize,
+        db: &Database,
+    ) -> Result<Page<Self>, Error> {
+        page::page(db, after, limit)
+    }
+
+    /// Iterates over all entries with keys in `range.start..range.end`.
+    fn iter_range<K: Into<Self::KeyType>>(
+        range: Range<K>,
+        db: &Database,
+    ) -> Result<Iter<'_, Self>, Error> {
+        let cf = db.cf_handle::<Self>()?;
+        let start: Self::KeyType = range.start.into();
+        let end: Self::KeyType = range.end.into();
+        let mut opts = iter::total_order_read_options();
+        opts.set_iterate_upper_bound(end.serialize());
+        let mut raw = db.rocksdb.raw_iterator_cf_opt(&cf, opts);
+        raw.seek(start.serialize());
+        Ok(Iter::new(db, raw))
+    }
+
+    /// Counts the entries of the collection exactly. This walks over every key,
+    /// but skips decoding the values.
+    fn count(db: &Database) -> Result<u64, Error> {
+        let mut raw = db.rocksdb.raw_iterator_cf(&db.cf_handle::<Self>()?);
+        raw.seek_to_first();
+        let mut count = 0;
+        while raw.valid() {
+            count += 1;
+            raw.next();
+        }
+        raw.status()?;
+        Ok(count)
+    }
+
+    /// Estimated number of entries, as tracked by RocksDB. Cheap, but can be
+    /// off by a lot when keys were overwritten or deleted recently.
+    fn approximate_count(db: &Database) -> Result<u64, Error> {
+        Ok(db
+            .rocksdb
+            .property_int_value_cf(
+                &db.cf_handle::<Self>()?,
+                rocksdb::properties::ESTIMATE_NUM_KEYS,
+            )?
+            .unwrap_or(0))
+    }
+
+    /// Estimated size in bytes of the collection's live data, on disk and in
+    /// memtables.
+    fn approximate_size(db: &Database) -> Result<u64, Error> {
+        let cf = db.cf_handle::<Self>()?;
+        let property = |name| -> Result<u64, Error> {
+            Ok(db.rocksdb.property_int_value_cf(&cf, name)?.unwrap_or(0))
+        };
+        Ok(property(rocksdb::properties::ESTIMATE_LIVE_DATA_SIZE)?
+            + property(rocksdb::properties::CUR_SIZE_ALL_MEM_TABLES)?)
+    }
+
+    /// Reads the integer RocksDB property `name`, one of
+    /// [`rocksdb::properties`], of the collection's column family.
+    fn property(name: &str, db: &Database) -> Result<Option<u64>, Error> {
+        stats::property::<Self>(db, name)
+    }
+
+    /// Reads the size estimates of the collection.
+    fn properties(db: &Database) -> Result<CollectionProperties, Error> {
+        stats::properties::<Self>(db)
+    }
+
+    /// Writes every entry to `writer` in key order as newline-delimited JSON,
+    /// one `[key, value]` array per line.
+    #[cfg(feature = "json")]
+    fn export_json(writer: impl std::io::Write, db: &Database) -> Result<(), Error>
+    where
+        Self::KeyType: Serialize,
+    {
+        json::export::<Self>(writer, db)
+    }
+
+    /// Stores the entries read from `reader` in the format written by
+    /// [`Collection::export_json`], replacing existing values, and returns
+    /// their number. Entries are committed in chunks, so a failed import may
+    /// be partially applied.
+    #[cfg(feature = "json")]
+    fn import_json(reader: impl std::io::BufRead, db: &Database) -> Result<usize, Error>
+    where
+        Self::KeyType: DeserializeOwned,
+    {
+        json::import::<Self>(reader, db)
+    }
+
+    /// Copies every entry of the collection in `src` into the collection in
+    /// `dst`, such as to clone an environment, and returns the number of
+    /// entries copied.
+    ///
+    /// Entries are read from a consistent view of `src` and bulk loaded into
+    /// `dst` with [`Database::bulk_loader`], replacing the values stored
+    /// under the same keys. Entries of `dst` with other keys are kept.
+    fn copy_to(src: &Database, dst: &Database) -> Result<u64, Error> {
+        let mut loader = dst.bulk_loader::<Self>()?;
+        let mut copied = 0;
+        for entry in Self::iter(src)? {
+            let (key, value) = entry?;
+            loader.add(key, &value)?;
+            copied += 1;
+        }
+        loader.finish()?;
+        Ok(copied)
+    }
+
+    /// Returns the first record (in primary key order) whose index `index`
+    /// has the given value.
+    fn get_by_index(index: &str, value: &[u8], db: &Database) -> Result<Option<Self>, Error> {
+        index::scan::<Self>(db, index, value)?
+            .next()
+            .transpose()
+            .map(|entry| entry.map(|(_, value)| value))
+    }
+
+    /// Iterates over all records whose index `index` has the given value.
+    fn scan_index<'db>(
+        index: &str,
+        value: &[u8],
+        db: &'db Database,
+    ) -> Result<IndexIter<'db, Self>, Error> {
+        index::scan(db, index, value)
+    }
+
+    /// Iterates over all records whose index `index` has a value in `range`,
+    /// in index value order. Index values order like their bytes, so the
+    /// values of the range and of [`Collection::index_keys`] must be
+    /// serialized the same order-preserving way, e.g. with [`Key::serialize`]
+    /// for numbers and timestamps.
+    ///
+    /// A record with several values of the index in `range` is yielded once
+    /// per value.
+    fn scan_index_range<'db>(
+        index: &str,
+        range: Range<&[u8]>,
+        db: &'db Database,
+    ) -> Result<IndexIter<'db, Self>, Error> {
+        index::scan_range(db, index, range.start.to_vec()..range.end.to_vec())
+    }
 }
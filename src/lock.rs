@@ -0,0 +1,201 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashSet},
+    hash::{Hash, Hasher},
+    sync::{Condvar, Mutex, MutexGuard, PoisonError},
+    time::Instant,
+};
+
+/// Number of shards of the lock table. Locking keys of different shards
+/// never contends on the same mutex.
+const SHARDS: usize = 256;
+
+/// `(CF, key)` pair a lock is taken on.
+type LockKey = (String, Vec<u8>);
+
+/// Lock table serializing read-modify-write cycles per `(CF, key)`. Only
+/// writers of the same key wait for each other.
+pub(crate) struct LockTable {
+    shards: Vec<Shard>,
+}
+
+#[derive(Default)]
+struct Shard {
+    /// Keys of this shard currently locked.
+    locked: Mutex<HashSet<LockKey>>,
+    /// Notified whenever keys of this shard are unlocked.
+    unlocked: Condvar,
+}
+
+/// Locks of every key locked by one operation, released when dropped.
+pub(crate) struct LockGuard<'a> {
+    table: &'a LockTable,
+    /// Locked keys grouped by shard, in ascending shard order.
+    keys: Vec<(usize, Vec<LockKey>)>,
+}
+
+impl LockTable {
+    pub(crate) fn new() -> Self {
+        Self {
+            shards: (0..SHARDS).map(|_| Shard::default()).collect(),
+        }
+    }
+
+    pub(crate) fn lock(&self, cf_name: &str, key: &[u8]) -> LockGuard<'_> {
+        self.lock_many([(cf_name, key)])
+    }
+
+    /// Locks all the given keys at once. Keys are only taken once all of them
+    /// are free, so concurrent calls can't deadlock.
+    pub(crate) fn lock_many<'k>(
+        &self,
+        keys: impl IntoIterator<Item = (&'k str, &'k [u8])>,
+    ) -> LockGuard<'_> {
+        let mut flat: Vec<(usize, LockKey)> = keys
+            .into_iter()
+            .map(|(cf_name, key)| (shard(cf_name, key), (cf_name.to_string(), key.to_vec())))
+            .collect();
+        flat.sort_unstable();
+        flat.dedup();
+        let mut keys: Vec<(usize, Vec<LockKey>)> = Vec::new();
+        for (shard, key) in flat {
+            match keys.last_mut() {
+                Some((last, shard_keys)) if *last == shard => shard_keys.push(key),
+                _ => keys.push((shard, vec![key])),
+            }
+        }
+        loop {
+            // Shard mutexes are taken in ascending order.
+            let mut locked: Vec<_> = keys
+                .iter()
+                .map(|(shard, _)| (*shard, self.locked(*shard)))
+                .collect();
+            let held = keys
+                .iter()
+                .zip(&locked)
+                .position(|((_, shard_keys), (_, locked))| {
+                    shard_keys.iter().any(|key| locked.contains(key))
+                });
+            let Some(held) = held else {
+                for ((_, shard_keys), (_, locked)) in keys.iter().zip(&mut locked) {
+                    locked.extend(shard_keys.iter().cloned());
+                }
+                drop(locked);
+                return LockGuard { table: self, keys };
+            };
+            // Waits for the held key with only its shard locked, then starts
+            // over.
+            let (shard, guard) = locked.swap_remove(held);
+            drop(locked);
+            drop(self.wait(shard, guard, None));
+        }
+    }
+
+    /// Locks `(cf_name, key)`, giving up at `deadline`. Keys locked one at a
+    /// time can be acquired in any order, so a caller holding some of them
+    /// must be ready to time out instead of deadlocking.
+    pub(crate) fn lock_until(
+        &self,
+        cf_name: &str,
+        key: &[u8],
+        deadline: Instant,
+    ) -> Option<LockGuard<'_>> {
+        let shard = shard(cf_name, key);
+        let key = (cf_name.to_string(), key.to_vec());
+        let mut locked = self.locked(shard);
+        while locked.contains(&key) {
+            locked = self.wait(shard, locked, Some(deadline))?;
+        }
+        locked.insert(key.clone());
+        Some(LockGuard {
+            table: self,
+            keys: vec![(shard, vec![key])],
+        })
+    }
+
+    fn locked(&self, shard: usize) -> MutexGuard<'_, HashSet<LockKey>> {
+        // The sets are only changed by code that can't panic halfway.
+        self.shards[shard]
+            .locked
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Waits until keys of `shard` are unlocked, or returns `None` once
+    /// `deadline` has passed.
+    fn wait<'a>(
+        &self,
+        shard: usize,
+        locked: MutexGuard<'a, HashSet<LockKey>>,
+        deadline: Option<Instant>,
+    ) -> Option<MutexGuard<'a, HashSet<LockKey>>> {
+        let unlocked = &self.shards[shard].unlocked;
+        match deadline {
+            None => Some(
+                unlocked
+                    .wait(locked)
+                    .unwrap_or_else(PoisonError::into_inner),
+            ),
+            Some(deadline) => {
+                let timeout = deadline.checked_duration_since(Instant::now())?;
+                let (locked, _) = unlocked
+                    .wait_timeout(locked, timeout)
+                    .unwrap_or_else(PoisonError::into_inner);
+                Some(locked)
+            }
+        }
+    }
+}
+
+impl Drop for LockGuard<'_> {
+    fn drop(&mut self) {
+        for (shard, keys) in &self.keys {
+            let mut locked = self.table.locked(*shard);
+            for key in keys {
+                locked.remove(key);
+            }
+            drop(locked);
+            self.table.shards[*shard].unlocked.notify_all();
+        }
+    }
+}
+
+fn shard(cf_name: &str, key: &[u8]) -> usize {
+    let mut hasher = DefaultHasher::new();
+    cf_name.hash(&mut hasher);
+    key.hash(&mut hasher);
+    (hasher.finish() % SHARDS as u64) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn only_the_same_key_waits() {
+        let table = LockTable::new();
+        let deadline = Instant::now() + Duration::from_millis(50);
+        let _a = table.lock("cf", b"a");
+        // Every other key is free, whichever shard it falls in.
+        for i in 0..(4 * SHARDS as u32) {
+            assert!(table.lock_until("cf", &i.to_be_bytes(), deadline).is_some());
+        }
+        assert!(table.lock_until("other", b"a", deadline).is_some());
+        assert!(table.lock_until("cf", b"a", deadline).is_none());
+    }
+
+    #[test]
+    fn unlocked_keys_wake_up_waiters() {
+        let table = LockTable::new();
+        let guard = table.lock_many([("cf", &b"a"[..]), ("cf", b"b"), ("cf", b"a")]);
+        std::thread::scope(|scope| {
+            let waiter = scope.spawn(|| drop(table.lock_many([("cf", &b"b"[..]), ("cf", b"c")])));
+            std::thread::sleep(Duration::from_millis(10));
+            drop(guard);
+            waiter.join().unwrap();
+        });
+        let deadline = Instant::now() + Duration::from_millis(50);
+        assert!(table.lock_until("cf", b"c", deadline).is_some());
+    }
+}
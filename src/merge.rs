@@ -0,0 +1,80 @@
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{value, Collection, Database, DatabaseBuilder, Error, Key, Options};
+
+/// Collection whose values can be updated with RocksDB merge operands instead
+/// of a read-modify-write cycle.
+///
+/// [`MergeCollection::merge`] only appends the operand; operands are folded
+/// into the stored value with [`MergeCollection::apply_merge`] lazily, on
+/// reads and during compaction. The collection has to be registered with
+/// [`DatabaseBuilder::add_merge_collection`].
+///
+/// Merges take the lock of their key, so they can be mixed with
+/// [`Collection::modify`] and other read-modify-write cycles on the same key.
+/// Operands aren't seen by watchers, hooks or the audit log, which only learn
+/// of the merged value once it is written by something else.
+pub trait MergeCollection: Collection + 'static {
+    type MergeOp: Serialize + DeserializeOwned;
+
+    /// Applies one operand to the current value.
+    fn apply_merge(existing: Option<Self>, op: Self::MergeOp) -> Self;
+
+    fn merge<K: Into<Self::KeyType>>(
+        key: K,
+        op: Self::MergeOp,
+        db: &Database,
+    ) -> Result<(), Error> {
+        if !Self::INDEXES.is_empty() {
+            // Index maintenance needs the merged value, so there is nothing to
+            // gain over a plain read-modify-write.
+            return Self::modify(key, db, |value| {
+                *value = Some(Self::apply_merge(value.take(), op));
+            });
+        }
+        let key: Self::KeyType = key.into();
+        let key = key.serialize();
+        let op = rmp_serde::encode::to_vec(&op).map_err(Error::RmpEncode)?;
+        // Serialize with `modify`, which would otherwise overwrite the operand
+        // with a value read before it.
+        let _guard = db.locks.lock(Self::CF_NAME, &key);
+        db.rocksdb.merge_cf(&db.cf_handle::<Self>()?, &key, op)?;
+        Ok(())
+    }
+}
+
+impl DatabaseBuilder {
+    /// Adds a collection like [`DatabaseBuilder::add_collection`] and installs
+    /// its merge operator.
+    pub fn add_merge_collection<T: MergeCollection>(self) -> Self {
+        self.add_collection_with::<T>(configure_cf::<T>)
+    }
+}
+
+fn configure_cf<T: MergeCollection>(opts: &mut Options) {
+    T::cf_options(opts);
+    opts.set_merge_operator(
+        T::CF_NAME,
+        full_merge::<T>,
+        // Operands can't be combined without the value they apply to.
+        |_: &[u8], _: Option<&[u8]>, _: &rocksdb::MergeOperands| None,
+    );
+}
+
+/// Returning `None` makes RocksDB fail the read or compaction that needed
+/// the merge with a corruption error.
+fn full_merge<T: MergeCollection>(
+    key: &[u8],
+    existing: Option<&[u8]>,
+    operands: &rocksdb::MergeOperands,
+) -> Option<Vec<u8>> {
+    let mut value: Option<T> = match existing {
+        Some(existing) => Some(value::decode(key, existing).ok()?),
+        None => None,
+    };
+    for operand in operands {
+        let op = rmp_serde::decode::from_slice(operand).ok()?;
+        value = Some(T::apply_merge(value, op));
+    }
+    value::encode(key, &value?).ok()
+}
@@ -0,0 +1,80 @@
+use crate::{value, Collection, Database, DatabaseBuilder, Error, Key, Options};
+
+/// Collection whose values are state-based CRDTs: writes with
+/// [`Mergeable::merge_write`] are merged into the stored value instead of
+/// replacing it, so concurrent writers and replayed sync sources converge
+/// on the same value whatever order their writes land in.
+///
+/// [`Mergeable::merge`] must be commutative, associative and idempotent,
+/// such as taking the union of sets or the maximum of counters per replica.
+/// Writes are folded lazily by a merge operator, on reads and during
+/// compaction. The collection has to be registered with
+/// [`DatabaseBuilder::add_mergeable_collection`].
+///
+/// Merged writes take the lock of their key, so they can be mixed with
+/// [`Collection::modify`] on the same key.
+pub trait Mergeable: Collection + Clone + 'static {
+    /// Merges `other` into this value.
+    fn merge(&mut self, other: &Self);
+
+    /// Merges `value` into the value stored under `key`, or stores it if
+    /// there is none.
+    fn merge_write<K: Into<Self::KeyType>>(
+        key: K,
+        value: &Self,
+        db: &Database,
+    ) -> Result<(), Error> {
+        if !Self::INDEXES.is_empty() {
+            // Index maintenance needs the merged value, so there is nothing to
+            // gain over a plain read-modify-write.
+            return Self::modify(key, db, |stored| match stored {
+                Some(stored) => stored.merge(value),
+                None => *stored = Some(value.clone()),
+            });
+        }
+        let key: Self::KeyType = key.into();
+        let key = key.serialize();
+        let value = value::encode::<Self>(&key, value)?;
+        // Serialize with `modify`, which would otherwise overwrite the merged
+        // write with a value read before it.
+        let _guard = db.locks.lock(Self::CF_NAME, &key);
+        db.rocksdb.merge_cf(&db.cf_handle::<Self>()?, &key, value)?;
+        Ok(())
+    }
+}
+
+impl DatabaseBuilder {
+    /// Adds a collection like [`DatabaseBuilder::add_collection`] and installs
+    /// the merge operator folding its writes.
+    pub fn add_mergeable_collection<T: Mergeable>(self) -> Self {
+        self.add_collection_with::<T>(configure_cf::<T>)
+    }
+}
+
+fn configure_cf<T: Mergeable>(opts: &mut Options) {
+    T::cf_options(opts);
+    // Merges are associative, so writes can be combined before the value
+    // they apply to is known.
+    opts.set_merge_operator(T::CF_NAME, merge::<T>, merge::<T>);
+}
+
+/// Returning `None` makes RocksDB fail the read or compaction that needed
+/// the merge with a corruption error.
+fn merge<T: Mergeable>(
+    key: &[u8],
+    existing: Option<&[u8]>,
+    operands: &rocksdb::MergeOperands,
+) -> Option<Vec<u8>> {
+    let mut merged: Option<T> = match existing {
+        Some(existing) => Some(value::decode(key, existing).ok()?),
+        None => None,
+    };
+    for operand in operands {
+        let operand: T = value::decode(key, operand).ok()?;
+        match &mut merged {
+            Some(merged) => merged.merge(&operand),
+            None => merged = Some(operand),
+        }
+    }
+    value::encode(key, &merged?).ok()
+}
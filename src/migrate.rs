@@ -0,0 +1,442 @@
+use std::borrow::Cow;
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    index, iter, scrub, stats, value, write, Collection, CollectionInfo, Database, DatabaseBuilder,
+    Error,
+};
+
+/// Number of values rewritten per write batch while migrating.
+const CHUNK_SIZE: usize = 1024;
+
+/// Converts the MessagePack value of the previous schema version into the
+/// one of the next.
+type Step = Box<dyn Fn(&[u8]) -> Result<Vec<u8>, Error> + Send + Sync>;
+
+/// Runs migration steps on the value stored under a key of a collection, and
+/// encodes the result as a current value of the collection.
+type MigrateValue = fn(&[&Migration], &[u8], &[u8]) -> Result<Vec<u8>, Error>;
+
+/// Rebuilds the indexes of a collection from its migrated values.
+type RebuildIndexes = fn(&Database) -> Result<(), Error>;
+
+/// Deletes the entries of a collection with keys in `start..end`, or in
+/// `start..` if `end` is `None`.
+type DeleteRange = fn(&Database, &[u8], Option<&[u8]>) -> Result<(), Error>;
+
+/// Appends the entries of a collection whose values fail to decode.
+type Scrub = fn(&Database, &mut Vec<scrub::CorruptEntry>) -> Result<(), Error>;
+
+/// Reads the size estimates of a collection.
+type Properties = fn(&Database) -> Result<crate::CollectionProperties, Error>;
+
+struct Schema {
+    cf_name: &'static str,
+    key_type: &'static str,
+    indexes: &'static [&'static str],
+    version: u32,
+    properties: Properties,
+    rebuild_indexes: Option<RebuildIndexes>,
+    migrate_value: MigrateValue,
+    delete_range: DeleteRange,
+    scrub: Scrub,
+}
+
+struct Migration {
+    cf_name: &'static str,
+    version: u32,
+    step: Step,
+}
+
+/// Schema versions of the registered collections and their migrations.
+#[derive(Default)]
+pub(crate) struct Schemas {
+    schemas: Vec<Schema>,
+    migrations: Vec<Migration>,
+}
+
+fn version_key(cf_name: &str) -> String {
+    format!("rkyvdb.schema_version.{}", cf_name)
+}
+
+/// Last key migrated by an interrupted migration.
+fn progress_key(cf_name: &str) -> String {
+    format!("rkyvdb.migration_progress.{}", cf_name)
+}
+
+impl Schemas {
+    pub(crate) fn register<T: Collection>(&mut self) {
+        self.schemas.push(Schema {
+            cf_name: T::CF_NAME,
+            key_type: std::any::type_name::<T::KeyType>(),
+            indexes: T::INDEXES,
+            version: T::SCHEMA_VERSION,
+            properties: stats::properties::<T>,
+            rebuild_indexes: if T::INDEXES.is_empty() {
+                None
+            } else {
+                Some(index::rebuild::<T>)
+            },
+            migrate_value: migrate_value::<T>,
+            delete_range: write::delete_range::<T>,
+            scrub: scrub::scrub::<T>,
+        });
+    }
+
+    /// Deletes the entries with keys in `start..end` from every registered
+    /// collection.
+    pub(crate) fn delete_range(
+        &self,
+        db: &Database,
+        start: &[u8],
+        end: &[u8],
+    ) -> Result<(), Error> {
+        for schema in &self.schemas {
+            (schema.delete_range)(db, start, Some(end))?;
+        }
+        Ok(())
+    }
+
+    /// Describes every registered collection, in registration order.
+    pub(crate) fn infos(&self, db: &Database) -> Result<Vec<CollectionInfo>, Error> {
+        self.schemas
+            .iter()
+            .map(|schema| {
+                Ok(CollectionInfo {
+                    name: schema.cf_name,
+                    key_type: schema.key_type,
+                    indexes: schema.indexes,
+                    schema_version: schema.version,
+                    stored_schema_version: stored_version(&db.rocksdb, schema.cf_name)?,
+                    properties: (schema.properties)(db)?,
+                })
+            })
+            .collect()
+    }
+
+    pub(crate) fn scrub(
+        &self,
+        db: &Database,
+        corrupt: &mut Vec<scrub::CorruptEntry>,
+    ) -> Result<(), Error> {
+        for schema in &self.schemas {
+            (schema.scrub)(db, corrupt)?;
+        }
+        Ok(())
+    }
+
+    /// Records the schema version of the registered collections that hold no
+    /// values yet, which need no migration to it, so that later migrations
+    /// only run their own steps and older schemas refuse to open them.
+    pub(crate) fn stamp_empty(&self, db: &rocksdb::DB) -> Result<(), Error> {
+        for schema in &self.schemas {
+            if schema.version == 0 || db.get_pinned(version_key(schema.cf_name))?.is_some() {
+                continue;
+            }
+            let cf = db
+                .cf_handle(schema.cf_name)
+                .ok_or(Error::CollectionNotRegistered)?;
+            let mut raw = db.raw_iterator_cf_opt(&cf, iter::total_order_read_options());
+            raw.seek_to_first();
+            raw.status()?;
+            if !raw.valid() {
+                db.put(version_key(schema.cf_name), schema.version.to_be_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Fails if a collection was written by a newer schema than the one it
+    /// is registered with.
+    pub(crate) fn check(&self, db: &rocksdb::DB) -> Result<(), Error> {
+        for schema in &self.schemas {
+            if stored_version(db, schema.cf_name)? > schema.version {
+                return Err(Error::SchemaTooNew);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn stored_version(db: &rocksdb::DB, cf_name: &str) -> Result<u32, Error> {
+    Ok(match db.get_pinned(version_key(cf_name))? {
+        Some(version) => u32::from_be_bytes(
+            version
+                .as_ref()
+                .try_into()
+                .map_err(|_| Error::CorruptMetadata)?,
+        ),
+        // Collections created before versioning was introduced.
+        None => 0,
+    })
+}
+
+/// Runs `steps` on the MessagePack value stored under `key` of `T`, without
+/// its encryption and version stamp, then decodes the result as the current
+/// `T`, which the last step produced, and encodes it again.
+fn migrate_value<T: Collection>(
+    steps: &[&Migration],
+    key: &[u8],
+    stored: &[u8],
+) -> Result<Vec<u8>, Error> {
+    let bytes = value::unseal::<T>(T::CF_NAME, key, stored)?;
+    let (_, body) = value::split_version(&bytes);
+    let mut body = Cow::Borrowed(body);
+    for migration in steps {
+        body = Cow::Owned((migration.step)(&body)?);
+    }
+    value::encode(key, &value::deserialize::<T>(T::VERSION, &body)?)
+}
+
+impl DatabaseBuilder {
+    /// Registers the migration of collection `T` to schema version
+    /// `version`, run by [`Database::migrate`] on values stored by version
+    /// `version - 1`. Schema versions without a migration are assumed to be
+    /// compatible with the previous one.
+    ///
+    /// `Old` and `New` are the value types of the two versions; `New` is `T`
+    /// for the migration to [`Collection::SCHEMA_VERSION`]. Migrations convert
+    /// values as they are before encryption and without the stamp of
+    /// [`Collection::VERSION`], and the migrated values are stored like any
+    /// other value of `T`.
+    pub fn add_migration<T, Old, New>(mut self, version: u32, migrate: fn(Old) -> New) -> Self
+    where
+        T: Collection,
+        Old: DeserializeOwned + 'static,
+        New: Serialize + 'static,
+    {
+        self.schemas.migrations.push(Migration {
+            cf_name: T::CF_NAME,
+            version,
+            step: Box::new(move |value| {
+                let old = rmp_serde::decode::from_slice(value)?;
+                Ok(rmp_serde::encode::to_vec(&migrate(old))?)
+            }),
+        });
+        self
+    }
+}
+
+impl Database {
+    /// Brings every registered collection up to its
+    /// [`Collection::SCHEMA_VERSION`], rewriting its values with the
+    /// migrations registered by [`DatabaseBuilder::add_migration`] and
+    /// rebuilding its indexes.
+    ///
+    /// Must be called right after opening, before the collections are used.
+    /// An interrupted migration resumes where it stopped.
+    pub fn migrate(&self) -> Result<(), Error> {
+        for schema in &self.schemas.schemas {
+            self.migrate_collection(schema)?;
+        }
+        Ok(())
+    }
+
+    fn migrate_collection(&self, schema: &Schema) -> Result<(), Error> {
+        let stored = stored_version(&self.rocksdb, schema.cf_name)?;
+        if stored >= schema.version {
+            return Ok(());
+        }
+        let mut steps: Vec<&Migration> = self
+            .schemas
+            .migrations
+            .iter()
+            .filter(|migration| {
+                migration.cf_name == schema.cf_name
+                    && migration.version > stored
+                    && migration.version <= schema.version
+            })
+            .collect();
+        steps.sort_by_key(|migration| migration.version);
+        if !steps.is_empty() {
+            let cf = self
+                .rocksdb
+                .cf_handle(schema.cf_name)
+                .ok_or(Error::CollectionNotRegistered)?;
+            let mut resume_after = self.rocksdb.get(progress_key(schema.cf_name))?;
+            loop {
+                let mut raw = self
+                    .rocksdb
+                    .raw_iterator_cf_opt(&cf, iter::total_order_read_options());
+                match &resume_after {
+                    Some(last) => {
+                        raw.seek(last);
+                        if raw.key() == Some(last) {
+                            raw.next();
+                        }
+                    }
+                    None => raw.seek_to_first(),
+                }
+                let mut batch = rocksdb::WriteBatch::default();
+                let mut last = None;
+                while let (Some(key), Some(value)) = (raw.key(), raw.value()) {
+                    batch.put_cf(&cf, key, (schema.migrate_value)(&steps, key, value)?);
+                    last = Some(key.to_vec());
+                    if batch.len() == CHUNK_SIZE {
+                        break;
+                    }
+                    raw.next();
+                }
+                raw.status()?;
+                let Some(last) = last else { break };
+                // Recorded with the chunk, so that a crash can't let values be
+                // migrated twice.
+                batch.put(progress_key(schema.cf_name), &last);
+                self.rocksdb.write(batch)?;
+                resume_after = Some(last);
+            }
+            if let Some(rebuild_indexes) = schema.rebuild_indexes {
+                rebuild_indexes(self)?;
+            }
+        }
+        let mut batch = rocksdb::WriteBatch::default();
+        batch.delete(progress_key(schema.cf_name));
+        batch.put(version_key(schema.cf_name), schema.version.to_be_bytes());
+        self.rocksdb.write(batch)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use serde::Deserialize;
+
+    use super::*;
+    use crate::{temp::TempDir, Key};
+
+    #[derive(Serialize, Deserialize)]
+    struct PersonV0 {
+        name: String,
+    }
+
+    impl Collection for PersonV0 {
+        type KeyType = u32;
+        const CF_NAME: &'static str = "people";
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Person {
+        name: String,
+        age: u32,
+    }
+
+    impl Collection for Person {
+        type KeyType = u32;
+        const CF_NAME: &'static str = "people";
+        const SCHEMA_VERSION: u32 = 1;
+        const VERSION: u32 = 1;
+    }
+
+    fn open_migrated(path: &str) -> Result<Database, Error> {
+        Database::build()
+            .add_collection::<Person>()
+            .add_migration::<Person, PersonV0, Person>(1, |old| Person {
+                name: old.name,
+                age: 0,
+            })
+            .open(path)
+    }
+
+    #[test]
+    fn migrations_rewrite_every_value() {
+        let dir = TempDir::new();
+        let path = dir.path().to_str().unwrap();
+        {
+            let db = Database::open_with_collections::<(PersonV0,)>(path).unwrap();
+            for id in 0..(CHUNK_SIZE as u32 + 10) {
+                let name = id.to_string();
+                PersonV0::put(id, &PersonV0 { name }, &db).unwrap();
+            }
+        }
+        {
+            let db = open_migrated(path).unwrap();
+            db.migrate().unwrap();
+            let person = Person {
+                name: "1030".to_string(),
+                age: 0,
+            };
+            assert_eq!(Person::get(1030u32, &db).unwrap(), Some(person));
+            assert_eq!(Person::count(&db).unwrap(), CHUNK_SIZE as u64 + 10);
+            // Migrated values are stamped with the current version.
+            let cf = db.cf_handle::<Person>().unwrap();
+            let stored = db
+                .rocksdb
+                .get_cf(&cf, Key::serialize(&1u32))
+                .unwrap()
+                .unwrap();
+            assert_eq!(value::split_version(&stored).0, 1);
+        }
+        let db = open_migrated(path).unwrap();
+        db.migrate().unwrap();
+        assert_eq!(Person::get(0u32, &db).unwrap().unwrap().name, "0");
+        drop(db);
+        assert!(matches!(
+            Database::open_with_collections::<(PersonV0,)>(path),
+            Err(Error::SchemaTooNew)
+        ));
+    }
+
+    static STEPS_RUN: [AtomicUsize; 2] = [AtomicUsize::new(0), AtomicUsize::new(0)];
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct PersonV2 {
+        name: String,
+        age: u32,
+        email: Option<String>,
+    }
+
+    impl Collection for PersonV2 {
+        type KeyType = u32;
+        const CF_NAME: &'static str = "people";
+        const SCHEMA_VERSION: u32 = 2;
+    }
+
+    #[test]
+    fn new_collections_start_at_their_schema_version() {
+        let dir = TempDir::new();
+        let path = dir.path().to_str().unwrap();
+        {
+            let db = Database::open_with_collections::<(Person,)>(path).unwrap();
+            let person = Person {
+                name: "a".to_string(),
+                age: 1,
+            };
+            Person::put(1u32, &person, &db).unwrap();
+        }
+        assert!(matches!(
+            Database::open_with_collections::<(PersonV0,)>(path),
+            Err(Error::SchemaTooNew)
+        ));
+        let db = Database::build()
+            .add_collection::<PersonV2>()
+            .add_migration::<PersonV2, PersonV0, Person>(1, |old| {
+                STEPS_RUN[0].fetch_add(1, Ordering::Relaxed);
+                Person {
+                    name: old.name,
+                    age: 0,
+                }
+            })
+            .add_migration::<PersonV2, Person, PersonV2>(2, |old| {
+                STEPS_RUN[1].fetch_add(1, Ordering::Relaxed);
+                PersonV2 {
+                    name: old.name,
+                    age: old.age,
+                    email: None,
+                }
+            })
+            .open(path)
+            .unwrap();
+        db.migrate().unwrap();
+        assert_eq!(STEPS_RUN[0].load(Ordering::Relaxed), 0);
+        assert_eq!(STEPS_RUN[1].load(Ordering::Relaxed), 1);
+        let person = PersonV2 {
+            name: "a".to_string(),
+            age: 1,
+            email: None,
+        };
+        assert_eq!(PersonV2::get(1u32, &db).unwrap(), Some(person));
+    }
+}
@@ -0,0 +1,140 @@
+use std::marker::PhantomData;
+
+use crate::{iter, key, value, write, Collection, Database, Error, Key};
+
+/// View of the database confined to one namespace, such as the data of one
+/// tenant, created by [`Database::namespace`].
+///
+/// Every key read or written through it is prefixed with the namespace, so
+/// namespaces sharing a collection never see each other's entries. A
+/// collection should then only be accessed through namespaces: indexes,
+/// hooks and the methods of [`Collection`] see the prefixed keys, which
+/// don't decode as the collection's key type. Indexes span all namespaces,
+/// so a unique index value can only be used by one of them.
+pub struct Namespace<'db> {
+    db: &'db Database,
+    prefix: Vec<u8>,
+}
+
+impl Database {
+    pub fn namespace(&self, name: &str) -> Namespace<'_> {
+        Namespace {
+            db: self,
+            prefix: prefix(name),
+        }
+    }
+
+    /// Deletes every entry of the namespace `name` from all collections
+    /// registered with the builder.
+    pub fn delete_namespace(&self, name: &str) -> Result<(), Error> {
+        let start = prefix(name);
+        // Encoded names end with a 0 byte, so they always have an upper bound.
+        let end = iter::prefix_upper_bound(&start).ok_or(Error::InvalidKey)?;
+        self.schemas.delete_range(self, &start, &end)
+    }
+}
+
+impl<'db> Namespace<'db> {
+    pub fn get<T: Collection>(&self, key: impl Into<T::KeyType>) -> Result<Option<T>, Error> {
+        self.db.get_serialized(&self.key::<T>(key))
+    }
+
+    pub fn put<T: Collection>(&self, key: impl Into<T::KeyType>, value: &T) -> Result<(), Error> {
+        let key = self.key::<T>(key);
+        let write = write::PendingWrite::put(&key, value)?;
+        let _guard = self.db.locks.lock(T::CF_NAME, &key);
+        write::commit(self.db, vec![write])
+    }
+
+    pub fn delete<T: Collection>(&self, key: impl Into<T::KeyType>) -> Result<(), Error> {
+        let key = self.key::<T>(key);
+        let _guard = self.db.locks.lock(T::CF_NAME, &key);
+        write::commit(self.db, vec![write::PendingWrite::delete::<T>(&key)])
+    }
+
+    /// Like [`Collection::modify`], within the namespace.
+    pub fn modify<T: Collection, R>(
+        &self,
+        key: impl Into<T::KeyType>,
+        modifier: impl FnOnce(&mut Option<T>) -> R,
+    ) -> Result<R, Error> {
+        let key = self.key::<T>(key);
+        let _guard = self.db.locks.lock(T::CF_NAME, &key);
+        let old_value = self
+            .db
+            .rocksdb
+            .get_pinned_cf(&self.db.cf_handle::<T>()?, &key)?;
+        let mut value = old_value
+            .as_deref()
+            .map(|value| value::decode(&key, value))
+            .transpose()?;
+        let result = modifier(&mut value);
+        if value::matches(T::CF_NAME, &key, old_value.as_deref(), value.as_ref())? {
+            return Ok(result);
+        }
+        let write = match value {
+            Some(value) => write::PendingWrite::put(&key, &value)?,
+            None => write::PendingWrite::delete::<T>(&key),
+        };
+        write::commit(self.db, vec![write])?;
+        Ok(result)
+    }
+
+    /// Iterates over the entries of `T` in the namespace, in key order.
+    pub fn iter<T: Collection>(&self) -> Result<NamespaceIter<'db, T>, Error> {
+        let mut raw = self.db.rocksdb.raw_iterator_cf_opt(
+            &self.db.cf_handle::<T>()?,
+            iter::prefix_read_options(&self.prefix, T::PREFIX_LENGTH),
+        );
+        raw.seek(&self.prefix);
+        Ok(NamespaceIter {
+            raw,
+            prefix_len: self.prefix.len(),
+            done: false,
+            _marker: PhantomData,
+        })
+    }
+
+    fn key<T: Collection>(&self, key: impl Into<T::KeyType>) -> Vec<u8> {
+        [&self.prefix[..], &key.into().serialize()].concat()
+    }
+}
+
+/// Iterator over the entries of a collection in a namespace, created by
+/// [`Namespace::iter`].
+pub struct NamespaceIter<'db, T> {
+    raw: rocksdb::DBRawIterator<'db>,
+    prefix_len: usize,
+    done: bool,
+    _marker: PhantomData<T>,
+}
+
+impl<'db, T: Collection> Iterator for NamespaceIter<'db, T> {
+    type Item = Result<(T::KeyType, T), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let (key, value) = match (self.raw.key(), self.raw.value()) {
+            (Some(key), Some(value)) => (key, value),
+            _ => {
+                self.done = true;
+                return self.raw.status().err().map(|err| Err(err.into()));
+            }
+        };
+        let entry = T::KeyType::deserialize(&key[self.prefix_len..])
+            .ok_or(Error::InvalidKey)
+            .and_then(|deserialized| Ok((deserialized, value::decode(key, value)?)));
+        self.raw.next();
+        Some(entry)
+    }
+}
+
+/// Keys of a namespace start with its encoded name, which no other encoded
+/// name starts with.
+fn prefix(name: &str) -> Vec<u8> {
+    let mut prefix = Vec::new();
+    key::encode_component(&mut prefix, name.as_bytes());
+    prefix
+}
@@ -0,0 +1,81 @@
+use std::{fmt, str::FromStr};
+
+use crate::{iter, Collection, Database, Error, Iter, Key};
+
+/// Entries returned by [`Collection::page`].
+pub struct Page<T: Collection> {
+    pub entries: Vec<(T::KeyType, T)>,
+    /// Position after the last entry, or `None` if no entries follow.
+    pub cursor: Option<Cursor>,
+}
+
+/// Position in a collection to continue paging from.
+///
+/// Cursors are the serialized key of the last entry of a page. They print as
+/// hex and parse back with [`str::parse`], so they can be handed to clients
+/// as tokens; [`Cursor::key`] turns them back into the key to pass to
+/// [`Collection::page`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cursor(Vec<u8>);
+
+impl Cursor {
+    pub fn key<K: Key>(&self) -> Result<K, Error> {
+        K::deserialize(&self.0).ok_or(Error::InvalidKey)
+    }
+}
+
+impl fmt::Display for Cursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.iter().try_for_each(|byte| write!(f, "{byte:02x}"))
+    }
+}
+
+impl FromStr for Cursor {
+    type Err = Error;
+
+    fn from_str(hex: &str) -> Result<Self, Error> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| {
+                hex.get(i..i + 2)
+                    .filter(|byte| byte.bytes().all(|digit| digit.is_ascii_hexdigit()))
+                    .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                    .ok_or(Error::InvalidCursor)
+            })
+            .collect::<Result<_, _>>()
+            .map(Cursor)
+    }
+}
+
+pub(crate) fn page<T: Collection>(
+    db: &Database,
+    after: Option<T::KeyType>,
+    limit: usize,
+) -> Result<Page<T>, Error> {
+    let mut raw = db
+        .rocksdb
+        .raw_iterator_cf_opt(&db.cf_handle::<T>()?, iter::total_order_read_options());
+    match after {
+        Some(after) => {
+            let after = after.serialize();
+            raw.seek(&after);
+            if raw.key() == Some(&after) {
+                raw.next();
+            }
+        }
+        None => raw.seek_to_first(),
+    }
+    // One more entry than asked for tells whether the page is the last one.
+    let mut entries = Iter::<T>::new(db, raw)
+        .take(limit.saturating_add(1))
+        .collect::<Result<Vec<_>, _>>()?;
+    let cursor = if entries.len() > limit {
+        entries.truncate(limit);
+        entries
+            .last()
+            .map(|(key, _)| Cursor(key.serialize().into_owned()))
+    } else {
+        None
+    };
+    Ok(Page { entries, cursor })
+}
@@ -0,0 +1,118 @@
+//! Capture of RocksDB perf context counters around operations.
+//!
+//! The perf context is thread-local and counts from whenever it was last
+//! reset, so captures take the difference between the counters at their
+//! start and end instead of resetting them, which lets them nest.
+
+use std::cell::Cell;
+
+use rocksdb::perf::{self, PerfContext, PerfMetric, PerfStatsLevel};
+
+thread_local! {
+    /// Number of captures running on this thread, which keep the perf stats
+    /// level raised until the last one ends.
+    static CAPTURES: Cell<u32> = const { Cell::new(0) };
+}
+
+macro_rules! perf_counters {
+    ($($(#[$doc:meta])* $field:ident: $metric:ident,)*) => {
+        /// RocksDB perf context counters of the work done by some operations,
+        /// returned by [`with_perf_context`]. Times are in nanoseconds.
+        #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+        pub struct PerfCounters {
+            $($(#[$doc])* pub $field: u64,)*
+        }
+
+        impl PerfCounters {
+            fn read(context: &PerfContext) -> Self {
+                Self {
+                    $($field: context.metric(PerfMetric::$metric),)*
+                }
+            }
+
+            fn since(&self, start: &Self) -> Self {
+                Self {
+                    $($field: self.$field.wrapping_sub(start.$field),)*
+                }
+            }
+
+            #[cfg(feature = "tracing")]
+            pub(crate) fn add(&mut self, other: &Self) {
+                $(self.$field = self.$field.wrapping_add(other.$field);)*
+            }
+        }
+    };
+}
+
+perf_counters! {
+    user_key_comparisons: UserKeyComparisonCount,
+    block_cache_hits: BlockCacheHitCount,
+    /// Blocks read from SST files, missing the block cache.
+    block_reads: BlockReadCount,
+    block_read_bytes: BlockReadByte,
+    block_read_time: BlockReadTime,
+    memtable_gets: GetFromMemtableCount,
+    memtable_seeks: SeekOnMemtableCount,
+    /// Seeks of the iterators over memtables and SST files.
+    child_seeks: SeekChildSeekCount,
+    /// Entries skipped by iterators because they were overwritten.
+    internal_keys_skipped: InternalKeySkippedCount,
+    /// Deletion tombstones skipped by iterators.
+    internal_deletes_skipped: InternalDeleteSkippedCount,
+    bloom_sst_hits: BloomSstHitCount,
+    bloom_sst_misses: BloomSstMissCount,
+    /// Time spent waiting for the database mutex.
+    db_mutex_wait_time: DbMutexLockNanos,
+    write_wal_time: WriteWalTime,
+    write_memtable_time: WriteMemtableTime,
+    /// Time writes were delayed by a write stall.
+    write_delay_time: WriteDelayTime,
+}
+
+/// Runs `f` and returns its result together with the RocksDB perf context
+/// counters of the work it did on the current thread, for profiling hot
+/// paths. Work `f` hands off to other threads isn't counted.
+///
+/// The counters include time measurements, which slow `f` down a little.
+pub fn with_perf_context<R>(f: impl FnOnce() -> R) -> (R, PerfCounters) {
+    let capture = Capture::start();
+    let result = f();
+    (result, capture.counters())
+}
+
+/// Perf context counters of the current thread from its creation on. It
+/// must be dropped on the thread it was created on.
+pub(crate) struct Capture {
+    context: PerfContext,
+    start: PerfCounters,
+}
+
+impl Capture {
+    pub(crate) fn start() -> Self {
+        CAPTURES.with(|captures| {
+            if captures.get() == 0 {
+                perf::set_perf_stats(PerfStatsLevel::EnableTime);
+            }
+            captures.set(captures.get() + 1);
+        });
+        let context = PerfContext::default();
+        let start = PerfCounters::read(&context);
+        Self { context, start }
+    }
+
+    /// Counters of the work done on this thread since the capture started.
+    pub(crate) fn counters(&self) -> PerfCounters {
+        PerfCounters::read(&self.context).since(&self.start)
+    }
+}
+
+impl Drop for Capture {
+    fn drop(&mut self) {
+        CAPTURES.with(|captures| {
+            captures.set(captures.get() - 1);
+            if captures.get() == 0 {
+                perf::set_perf_stats(PerfStatsLevel::Disable);
+            }
+        });
+    }
+}
@@ -0,0 +1,48 @@
+use std::{
+    sync::{mpsc, Arc},
+    thread,
+    time::Duration,
+};
+
+use crate::Database;
+
+/// Background thread running a task on a database at a fixed interval,
+/// until it is dropped or the database is closed.
+pub(crate) struct Periodic {
+    stop: Option<mpsc::Sender<()>>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Periodic {
+    pub(crate) fn spawn(
+        db: &Database,
+        interval: Duration,
+        mut task: impl FnMut(&Database) + Send + 'static,
+    ) -> Self {
+        let (stop, stopped) = mpsc::channel();
+        // The task mustn't keep the database open.
+        let db = Arc::downgrade(&db.0);
+        let thread = thread::spawn(move || {
+            while let Err(mpsc::RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                match db.upgrade() {
+                    Some(db) => task(&Database(db)),
+                    None => return,
+                }
+            }
+        });
+        Self {
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for Periodic {
+    fn drop(&mut self) {
+        // Disconnecting the channel wakes the thread up.
+        self.stop.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    lock::LockGuard,
+    value,
+    write::{self, PendingWrite},
+    Collection, Database, Error, Key,
+};
+
+/// How long a transaction waits for a lock by default.
+const DEFAULT_LOCK_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Transaction locking every key it reads or writes until it is committed or
+/// dropped, created by [`Database::pessimistic_transaction`].
+///
+/// Unlike an optimistic [`Transaction`](crate::Transaction), it never
+/// conflicts on commit: a concurrent writer of the same keys waits for it
+/// instead, which suits heavily contended keys where optimistic retries would
+/// keep failing. Taking a lock held by someone else for longer than the lock
+/// timeout fails with [`Error::LockTimeout`], which also breaks deadlocks
+/// between transactions locking keys in different orders.
+///
+/// Locks are the ones taken by [`Collection::modify`], so writers of the
+/// locked keys outside of the transaction wait for it too.
+pub struct PessimisticTransaction<'db> {
+    db: &'db Database,
+    lock_timeout: Duration,
+    locks: HashMap<(&'static str, Vec<u8>), LockGuard<'db>>,
+    writes: Vec<PendingWrite>,
+}
+
+impl Database {
+    pub fn pessimistic_transaction(&self) -> PessimisticTransaction<'_> {
+        PessimisticTransaction {
+            db: self,
+            lock_timeout: DEFAULT_LOCK_TIMEOUT,
+            locks: HashMap::new(),
+            writes: Vec::new(),
+        }
+    }
+}
+
+impl<'db> PessimisticTransaction<'db> {
+    /// Waits up to `timeout` for each lock instead of one second.
+    pub fn lock_timeout(mut self, timeout: Duration) -> Self {
+        self.lock_timeout = timeout;
+        self
+    }
+
+    pub fn get<T: Collection>(&mut self, key: impl Into<T::KeyType>) -> Result<Option<T>, Error> {
+        self.get_serialized(&key.into().serialize())
+    }
+
+    pub fn put<T: Collection>(
+        &mut self,
+        key: impl Into<T::KeyType>,
+        value: &T,
+    ) -> Result<(), Error> {
+        let key: T::KeyType = key.into();
+        let key = key.serialize();
+        self.lock(T::CF_NAME, &key)?;
+        self.writes.push(PendingWrite::put(&key, value)?);
+        Ok(())
+    }
+
+    pub fn delete<T: Collection>(&mut self, key: impl Into<T::KeyType>) -> Result<(), Error> {
+        let key: T::KeyType = key.into();
+        let key = key.serialize();
+        self.lock(T::CF_NAME, &key)?;
+        self.writes.push(PendingWrite::delete::<T>(&key));
+        Ok(())
+    }
+
+    /// Updates the value of `T` stored under `key` like [`Collection::modify`].
+    pub fn modify<T: Collection, R>(
+        &mut self,
+        key: impl Into<T::KeyType>,
+        modifier: impl FnOnce(&mut Option<T>) -> R,
+    ) -> Result<R, Error> {
+        let key: T::KeyType = key.into();
+        let key = key.serialize();
+        let mut value = self.get_serialized(&key)?;
+        let result = modifier(&mut value);
+        self.writes.push(match value {
+            Some(value) => PendingWrite::put(&key, &value)?,
+            None => PendingWrite::delete::<T>(&key),
+        });
+        Ok(result)
+    }
+
+    /// Writes the staged writes atomically and releases the locks.
+    pub fn commit(self) -> Result<(), Error> {
+        write::commit(self.db, self.writes)
+    }
+
+    fn get_serialized<T: Collection>(&mut self, key: &[u8]) -> Result<Option<T>, Error> {
+        self.lock(T::CF_NAME, key)?;
+        match write::staged(&self.writes, T::CF_NAME, key) {
+            Some(staged) => staged.map(|staged| value::decode(key, staged)).transpose(),
+            None => self.db.get_serialized(key),
+        }
+    }
+
+    fn lock(&mut self, cf_name: &'static str, key: &[u8]) -> Result<(), Error> {
+        if let Entry::Vacant(entry) = self.locks.entry((cf_name, key.to_vec())) {
+            let deadline = Instant::now() + self.lock_timeout;
+            entry.insert(
+                self.db
+                    .locks
+                    .lock_until(cf_name, key, deadline)
+                    .ok_or(Error::LockTimeout)?,
+            );
+        }
+        Ok(())
+    }
+}
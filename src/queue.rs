@@ -0,0 +1,330 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{iter, CfOptions, Database, DatabaseBuilder, Error};
+
+/// Number of ids per block of consumed entries. Entries are removed with one
+/// range deletion once the head of the queue leaves their block.
+const CONSUMED_BLOCK: u64 = 1024;
+
+/// Durable FIFO queue, such as a queue of jobs for background workers.
+///
+/// Entries are keyed by ids from the sequence of the queue, so they are
+/// popped in the order they were pushed. Popping an entry only moves the
+/// persisted head of the queue past it. The queue has to be registered with
+/// [`DatabaseBuilder::add_queue`].
+///
+/// Workers processing entries as jobs [`QueueCollection::claim`] them
+/// instead, which leases a job to one worker without removing it, so that
+/// it is handed out again unless acknowledged before the lease expires.
+/// Popping, peeking and counting ignore leases, so a queue is best used in
+/// one of the two ways.
+pub trait QueueCollection: Serialize + DeserializeOwned + Sized {
+    const CF_NAME: &'static str;
+
+    /// Appends `value` to the back of the queue and returns its id.
+    fn push(value: &Self, db: &Database) -> Result<u64, Error> {
+        let value = rmp_serde::encode::to_vec(value).map_err(Error::RmpEncode)?;
+        let cf = cf_handle(db, Self::CF_NAME)?;
+        // Ids are allocated and written under the queue lock, so entries
+        // can't appear behind the head.
+        let _guard = db.locks.lock(Self::CF_NAME, &[]);
+        let id = db.allocate_id(Self::CF_NAME)?;
+        db.rocksdb.put_cf(&cf, id.to_be_bytes(), value)?;
+        Ok(id)
+    }
+
+    /// Removes and returns the entry at the front of the queue.
+    fn pop_front(db: &Database) -> Result<Option<Self>, Error> {
+        let cf = cf_handle(db, Self::CF_NAME)?;
+        let _guard = db.locks.lock(Self::CF_NAME, &[]);
+        let head = head(db, Self::CF_NAME)?;
+        let (id, value) = match front(db, &cf, head)? {
+            Some(front) => front,
+            None => return Ok(None),
+        };
+        let value = decode(&value)?;
+        let mut batch = rocksdb::WriteBatch::default();
+        stage_head(&mut batch, &cf, Self::CF_NAME, head, id + 1);
+        db.rocksdb.write(batch)?;
+        Ok(Some(value))
+    }
+
+    /// Returns the entry at the front of the queue without removing it.
+    fn peek(db: &Database) -> Result<Option<Self>, Error> {
+        let cf = cf_handle(db, Self::CF_NAME)?;
+        match front(db, &cf, head(db, Self::CF_NAME)?)? {
+            Some((_, value)) => Ok(Some(decode(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Leases the oldest job not leased to a worker to the worker
+    /// `worker_id` for `lease`, and returns it with its id. The job stays
+    /// hidden from other claims until the lease expires or is released with
+    /// [`QueueCollection::ack`] or [`QueueCollection::nack`].
+    fn claim(
+        worker_id: &str,
+        lease: Duration,
+        db: &Database,
+    ) -> Result<Option<(u64, Self)>, Error> {
+        let cf = cf_handle(db, Self::CF_NAME)?;
+        let _guard = db.locks.lock(Self::CF_NAME, &[]);
+        let now = now_millis();
+        let mut raw = db
+            .rocksdb
+            .raw_iterator_cf_opt(&cf, iter::total_order_read_options());
+        raw.seek(head(db, Self::CF_NAME)?.to_be_bytes());
+        while let (Some(key), Some(value)) = (raw.key(), raw.value()) {
+            let id = decode_id(key)?;
+            let leased = match db.rocksdb.get_pinned(lease_key(Self::CF_NAME, id))? {
+                Some(lease) => decode_lease(&lease)?.0 > now,
+                None => false,
+            };
+            if !leased {
+                let value = decode(value)?;
+                let expires_at = now.saturating_add(lease.as_millis() as u64);
+                let lease = [&expires_at.to_be_bytes()[..], worker_id.as_bytes()].concat();
+                db.rocksdb.put(lease_key(Self::CF_NAME, id), lease)?;
+                return Ok(Some((id, value)));
+            }
+            raw.next();
+        }
+        raw.status()?;
+        Ok(None)
+    }
+
+    /// Removes the job `id` leased to `worker_id` once processed. Returns
+    /// `false` and keeps the job if the lease expired and the job may have
+    /// been claimed again.
+    fn ack(worker_id: &str, id: u64, db: &Database) -> Result<bool, Error> {
+        let cf = cf_handle(db, Self::CF_NAME)?;
+        let _guard = db.locks.lock(Self::CF_NAME, &[]);
+        if !holds_lease(db, Self::CF_NAME, worker_id, id)? {
+            return Ok(false);
+        }
+        let mut batch = rocksdb::WriteBatch::default();
+        batch.delete_cf(&cf, id.to_be_bytes());
+        batch.delete(lease_key(Self::CF_NAME, id));
+        // Keep claims from walking over the jobs acknowledged at the front.
+        let head = head(db, Self::CF_NAME)?;
+        if front(db, &cf, head)?.is_some_and(|(front, _)| front == id) {
+            stage_head(&mut batch, &cf, Self::CF_NAME, head, id + 1);
+        }
+        db.rocksdb.write(batch)?;
+        Ok(true)
+    }
+
+    /// Releases the lease of `worker_id` on the job `id`, so that it can be
+    /// claimed again right away. Returns `false` if the lease expired.
+    fn nack(worker_id: &str, id: u64, db: &Database) -> Result<bool, Error> {
+        let _guard = db.locks.lock(Self::CF_NAME, &[]);
+        if !holds_lease(db, Self::CF_NAME, worker_id, id)? {
+            return Ok(false);
+        }
+        db.rocksdb.delete(lease_key(Self::CF_NAME, id))?;
+        Ok(true)
+    }
+
+    /// Counts the entries in the queue. This walks over every entry, but
+    /// skips decoding them.
+    fn len(db: &Database) -> Result<u64, Error> {
+        let cf = cf_handle(db, Self::CF_NAME)?;
+        let mut raw = db
+            .rocksdb
+            .raw_iterator_cf_opt(&cf, iter::total_order_read_options());
+        raw.seek(head(db, Self::CF_NAME)?.to_be_bytes());
+        let mut len = 0;
+        while raw.valid() {
+            len += 1;
+            raw.next();
+        }
+        raw.status()?;
+        Ok(len)
+    }
+}
+
+impl DatabaseBuilder {
+    pub fn add_queue<Q: QueueCollection>(mut self) -> Self {
+        self.cf_descriptors.push((
+            Q::CF_NAME.to_string(),
+            CfOptions::Default {
+                collection: |_| {},
+                configure: |_| {},
+                prefix_bloom: false,
+            },
+        ));
+        self
+    }
+}
+
+fn head_key(cf_name: &str) -> String {
+    format!("rkyvdb.queue_head.{}", cf_name)
+}
+
+/// Id of the first entry not popped yet, if it still exists.
+fn head(db: &Database, cf_name: &str) -> Result<u64, Error> {
+    match db.rocksdb.get_pinned(head_key(cf_name))? {
+        Some(head) => Ok(u64::from_be_bytes(
+            head.as_ref()
+                .try_into()
+                .map_err(|_| Error::CorruptMetadata)?,
+        )),
+        None => Ok(0),
+    }
+}
+
+/// Stages moving the head of the queue from `head` to `new_head`, removing
+/// the entries of the blocks it leaves.
+fn stage_head(
+    batch: &mut rocksdb::WriteBatch,
+    cf: &rocksdb::ColumnFamilyRef,
+    cf_name: &str,
+    head: u64,
+    new_head: u64,
+) {
+    batch.put(head_key(cf_name), new_head.to_be_bytes());
+    let block_start = new_head - new_head % CONSUMED_BLOCK;
+    if head < block_start {
+        batch.delete_range_cf(cf, 0u64.to_be_bytes(), block_start.to_be_bytes());
+    }
+}
+
+/// Leases are kept in the default column family, keyed by queue and job id.
+fn lease_key(cf_name: &str, id: u64) -> Vec<u8> {
+    [
+        format!("rkyvdb.lease.{}.", cf_name).as_bytes(),
+        &id.to_be_bytes(),
+    ]
+    .concat()
+}
+
+/// Splits a lease into its expiry in Unix milliseconds and its worker id.
+fn decode_lease(lease: &[u8]) -> Result<(u64, &[u8]), Error> {
+    if lease.len() < 8 {
+        return Err(Error::CorruptMetadata);
+    }
+    let (expires_at, worker_id) = lease.split_at(8);
+    let expires_at = u64::from_be_bytes(expires_at.try_into().map_err(|_| Error::CorruptMetadata)?);
+    Ok((expires_at, worker_id))
+}
+
+/// Whether the job `id` is leased to `worker_id`, and not expired.
+fn holds_lease(db: &Database, cf_name: &str, worker_id: &str, id: u64) -> Result<bool, Error> {
+    match db.rocksdb.get_pinned(lease_key(cf_name, id))? {
+        Some(lease) => {
+            let (expires_at, holder) = decode_lease(&lease)?;
+            Ok(expires_at > now_millis() && holder == worker_id.as_bytes())
+        }
+        None => Ok(false),
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_millis() as u64)
+}
+
+/// Id and encoded value of the first entry from `head` on.
+fn front(
+    db: &Database,
+    cf: &rocksdb::ColumnFamilyRef,
+    head: u64,
+) -> Result<Option<(u64, Vec<u8>)>, Error> {
+    let mut raw = db
+        .rocksdb
+        .raw_iterator_cf_opt(cf, iter::total_order_read_options());
+    raw.seek(head.to_be_bytes());
+    match (raw.key(), raw.value()) {
+        (Some(key), Some(value)) => Ok(Some((decode_id(key)?, value.to_vec()))),
+        _ => Ok(raw.status().map(|()| None)?),
+    }
+}
+
+fn decode_id(bytes: &[u8]) -> Result<u64, Error> {
+    Ok(u64::from_be_bytes(
+        bytes.try_into().map_err(|_| Error::InvalidKey)?,
+    ))
+}
+
+fn decode<Q: QueueCollection>(bytes: &[u8]) -> Result<Q, Error> {
+    rmp_serde::decode::from_slice(bytes).map_err(Error::RmpDecode)
+}
+
+fn cf_handle<'db>(
+    db: &'db Database,
+    cf_name: &str,
+) -> Result<rocksdb::ColumnFamilyRef<'db>, Error> {
+    db.rocksdb
+        .cf_handle(cf_name)
+        .ok_or(Error::CollectionNotRegistered)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Job(u32);
+
+    impl QueueCollection for Job {
+        const CF_NAME: &'static str = "jobs";
+    }
+
+    fn open() -> Database {
+        Database::build().add_queue::<Job>().open_temp().unwrap()
+    }
+
+    #[test]
+    fn claimed_jobs_are_hidden_until_released() {
+        let db = open();
+        let first = Job::push(&Job(1), &db).unwrap();
+        let second = Job::push(&Job(2), &db).unwrap();
+        let lease = Duration::from_secs(60);
+        assert_eq!(Job::claim("a", lease, &db).unwrap(), Some((first, Job(1))));
+        assert_eq!(Job::claim("b", lease, &db).unwrap(), Some((second, Job(2))));
+        assert_eq!(Job::claim("c", lease, &db).unwrap(), None);
+
+        assert!(!Job::nack("b", first, &db).unwrap());
+        assert!(Job::nack("b", second, &db).unwrap());
+        assert_eq!(Job::claim("c", lease, &db).unwrap(), Some((second, Job(2))));
+
+        assert!(!Job::ack("b", second, &db).unwrap());
+        assert!(Job::ack("a", first, &db).unwrap());
+        assert!(Job::ack("c", second, &db).unwrap());
+        assert_eq!(Job::len(&db).unwrap(), 0);
+        assert_eq!(Job::claim("a", lease, &db).unwrap(), None);
+    }
+
+    #[test]
+    fn expired_leases_are_claimed_again() {
+        let db = open();
+        let id = Job::push(&Job(1), &db).unwrap();
+        assert_eq!(
+            Job::claim("a", Duration::ZERO, &db).unwrap(),
+            Some((id, Job(1)))
+        );
+        let lease = Duration::from_secs(60);
+        assert_eq!(Job::claim("b", lease, &db).unwrap(), Some((id, Job(1))));
+        assert!(!Job::ack("a", id, &db).unwrap());
+        assert!(Job::ack("b", id, &db).unwrap());
+    }
+
+    #[test]
+    fn entries_pop_in_push_order() {
+        let db = open();
+        for i in 0..3 {
+            Job::push(&Job(i), &db).unwrap();
+        }
+        assert_eq!(Job::peek(&db).unwrap(), Some(Job(0)));
+        assert_eq!(Job::len(&db).unwrap(), 3);
+        for i in 0..3 {
+            assert_eq!(Job::pop_front(&db).unwrap(), Some(Job(i)));
+        }
+        assert_eq!(Job::pop_front(&db).unwrap(), None);
+    }
+}
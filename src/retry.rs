@@ -0,0 +1,41 @@
+use std::{thread, time::Duration};
+
+/// How often and how patiently to retry an operation that conflicted with a
+/// concurrent write, such as [`Collection::modify_optimistic`].
+///
+/// [`Collection::modify_optimistic`]: crate::Collection::modify_optimistic
+#[derive(Clone, Copy, Debug)]
+pub struct Retry {
+    retries: u32,
+    backoff: Duration,
+}
+
+impl Retry {
+    /// Retries up to `retries` times after the first attempt, right away.
+    pub fn times(retries: u32) -> Self {
+        Self {
+            retries,
+            backoff: Duration::ZERO,
+        }
+    }
+
+    /// Waits `backoff` before the first retry, and twice as long before each
+    /// of the following ones.
+    pub fn with_backoff(mut self, backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Waits before retry number `retry`, counted from 0, or returns `false`
+    /// if there are no retries left.
+    pub(crate) fn wait(&self, retry: u32) -> bool {
+        if retry >= self.retries {
+            return false;
+        }
+        let backoff = self.backoff.saturating_mul(1 << retry.min(31));
+        if !backoff.is_zero() {
+            thread::sleep(backoff);
+        }
+        true
+    }
+}
@@ -0,0 +1,73 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use rayon::prelude::*;
+
+use crate::{iter, Collection, Comparator, Database, Error, Iter};
+
+/// Calls `f` on every entry of `T`, splitting the keyspace into one range per
+/// thread of rayon's global pool and scanning the ranges on it, so that scans
+/// reuse the same threads. All ranges read from the same snapshot.
+pub(crate) fn par_scan<T: Collection>(
+    db: &Database,
+    f: &(impl Fn(T::KeyType, T) + Sync),
+) -> Result<(), Error> {
+    db.cf_handle::<T>()?;
+    let shards = rayon::current_num_threads();
+    let split_points = split_points::<T>(db, shards)?;
+    let mut bounds = Vec::with_capacity(split_points.len() + 1);
+    let mut start = None;
+    for split_point in split_points {
+        bounds.push((start, Some(split_point.clone())));
+        start = Some(split_point);
+    }
+    bounds.push((start, None));
+
+    let snapshot = db.rocksdb.snapshot();
+    let failed = AtomicBool::new(false);
+    bounds.into_par_iter().try_for_each(|(start, end)| {
+        let mut opts = iter::total_order_read_options();
+        if let Some(end) = end {
+            opts.set_iterate_upper_bound(end);
+        }
+        let mut raw = snapshot.raw_iterator_cf_opt(&db.cf_handle::<T>()?, opts);
+        match start {
+            Some(start) => raw.seek(start),
+            None => raw.seek_to_first(),
+        }
+        // Ranges already being scanned stop early once one fails.
+        for item in Iter::<T>::new(db, raw) {
+            if failed.load(Ordering::Relaxed) {
+                break;
+            }
+            match item {
+                Ok((key, value)) => f(key, value),
+                Err(err) => {
+                    failed.store(true, Ordering::Relaxed);
+                    return Err(err);
+                }
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Up to `shards - 1` keys splitting `T` into ranges of similar size on disk,
+/// taken from the boundaries of its SST files, in the order of
+/// [`Collection::COMPARATOR`].
+fn split_points<T: Collection>(db: &Database, shards: usize) -> Result<Vec<Vec<u8>>, Error> {
+    let mut keys: Vec<Vec<u8>> = db
+        .rocksdb
+        .live_files()?
+        .into_iter()
+        .filter(|file| file.column_family_name == T::CF_NAME)
+        .filter_map(|file| file.start_key)
+        .collect();
+    let compare = Comparator::of::<T>();
+    keys.sort_unstable_by(|a, b| compare(a, b));
+    keys.dedup_by(|a, b| compare(a, b).is_eq());
+    let mut split_points: Vec<Vec<u8>> = (1..shards)
+        .filter_map(|shard| keys.get(shard * keys.len() / shards).cloned())
+        .collect();
+    split_points.dedup();
+    Ok(split_points)
+}
@@ -0,0 +1,67 @@
+use std::time::Duration;
+
+use crate::{iter, periodic::Periodic, value, Collection, Database, Error};
+
+/// Entry whose value can't be decoded, found by [`Database::scrub`].
+#[derive(Debug)]
+pub struct CorruptEntry {
+    pub collection: &'static str,
+    /// Serialized key of the entry.
+    pub key: Vec<u8>,
+    pub error: Error,
+}
+
+/// Scrub running in the background, started by [`Database::spawn_scrubber`].
+/// Dropping it stops the scrubber.
+pub struct Scrubber {
+    _periodic: Periodic,
+}
+
+impl Database {
+    /// Reads and decodes every value of the collections registered with the
+    /// builder, and returns the entries that fail to decode.
+    ///
+    /// RocksDB verifies the checksums of the blocks read, so a damaged file
+    /// fails the scrub with an error instead; see [`Database::repair`].
+    pub fn scrub(&self) -> Result<Vec<CorruptEntry>, Error> {
+        let mut corrupt = Vec::new();
+        self.schemas.scrub(self, &mut corrupt)?;
+        Ok(corrupt)
+    }
+
+    /// Runs [`Database::scrub`] on a background thread every `interval`,
+    /// passing each result to `report`, until the returned [`Scrubber`] is
+    /// dropped or the database is closed.
+    pub fn spawn_scrubber(
+        &self,
+        interval: Duration,
+        mut report: impl FnMut(Result<Vec<CorruptEntry>, Error>) + Send + 'static,
+    ) -> Scrubber {
+        Scrubber {
+            _periodic: Periodic::spawn(self, interval, move |db| report(db.scrub())),
+        }
+    }
+}
+
+/// Appends the entries of `T` that fail to decode to `corrupt`.
+pub(crate) fn scrub<T: Collection>(
+    db: &Database,
+    corrupt: &mut Vec<CorruptEntry>,
+) -> Result<(), Error> {
+    let mut raw = db
+        .rocksdb
+        .raw_iterator_cf_opt(&db.cf_handle::<T>()?, iter::total_order_read_options());
+    raw.seek_to_first();
+    while let (Some(key), Some(bytes)) = (raw.key(), raw.value()) {
+        if let Err(error) = value::decode::<T>(key, bytes) {
+            corrupt.push(CorruptEntry {
+                collection: T::CF_NAME,
+                key: key.to_vec(),
+                error,
+            });
+        }
+        raw.next();
+    }
+    raw.status()?;
+    Ok(())
+}
@@ -0,0 +1,169 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex, PoisonError,
+};
+
+use tantivy::{
+    collector::TopDocs,
+    query::QueryParser,
+    schema::{Document, Field, Schema, INDEXED, STORED, TEXT},
+    Index, IndexReader, IndexWriter, ReloadPolicy, Term,
+};
+
+use crate::{value, Collection, Database, DatabaseBuilder, Error, Key};
+
+/// Memory budget of the index writer of each collection.
+const WRITER_HEAP_SIZE: usize = 15_000_000;
+
+/// Collection whose values can be searched by text with
+/// [`SearchableCollection::search`], once registered with
+/// [`DatabaseBuilder::add_search_index`].
+///
+/// The search index is held in memory and rebuilt from the stored values
+/// when the database is opened, so it suits collections that fit in memory.
+/// It follows the writes seen by [`DatabaseBuilder::on_commit`] hooks.
+pub trait SearchableCollection: Collection {
+    /// Text of this value to index, such as its text fields joined by
+    /// newlines.
+    fn text(&self) -> String;
+
+    /// Returns up to `limit` entries matching `query`, best matches first.
+    /// Queries use the tantivy query syntax, such as `rust AND rocksdb` or
+    /// `"exact phrase"`.
+    fn search(
+        query: &str,
+        limit: usize,
+        db: &Database,
+    ) -> Result<Vec<(Self::KeyType, Self)>, Error> {
+        let index = db
+            .hooks
+            .search
+            .get(Self::CF_NAME)
+            .ok_or(Error::CollectionNotRegistered)?;
+        let keys = index.search(query, limit)?;
+        let mut entries = Vec::with_capacity(keys.len());
+        for key in keys {
+            // The entry was removed after the search index was read.
+            if let Some(value) = db.get_serialized(&key)? {
+                let key = Self::KeyType::deserialize(&key).ok_or(Error::InvalidKey)?;
+                entries.push((key, value));
+            }
+        }
+        Ok(entries)
+    }
+}
+
+/// Search index of one collection.
+pub(crate) struct SearchIndex {
+    index: Index,
+    writer: Mutex<IndexWriter>,
+    reader: IndexReader,
+    key_field: Field,
+    text_field: Field,
+    /// Whether staged changes are not visible to the reader yet.
+    dirty: AtomicBool,
+    /// Indexes every stored value of the collection.
+    fill: fn(&Database, &SearchIndex) -> Result<(), Error>,
+}
+
+impl SearchIndex {
+    fn new<T: SearchableCollection>() -> tantivy::Result<Self> {
+        let mut schema = Schema::builder();
+        let key_field = schema.add_bytes_field("key", INDEXED | STORED);
+        let text_field = schema.add_text_field("text", TEXT);
+        let index = Index::create_in_ram(schema.build());
+        let writer = index.writer_with_num_threads(1, WRITER_HEAP_SIZE)?;
+        let reader = index
+            .reader_builder()
+            .reload_policy(ReloadPolicy::Manual)
+            .try_into()?;
+        Ok(SearchIndex {
+            index,
+            writer: Mutex::new(writer),
+            reader,
+            key_field,
+            text_field,
+            dirty: AtomicBool::new(false),
+            fill: fill::<T>,
+        })
+    }
+
+    /// Stages the change of `key` to `value`, `None` for a delete.
+    fn stage<T: SearchableCollection>(&self, key: &[u8], value: Option<&T>) -> Result<(), Error> {
+        let writer = self.writer.lock().unwrap_or_else(PoisonError::into_inner);
+        writer.delete_term(Term::from_field_bytes(self.key_field, key));
+        if let Some(value) = value {
+            let mut document = Document::new();
+            document.add_bytes(self.key_field, key);
+            document.add_text(self.text_field, value.text());
+            writer.add_document(document)?;
+        }
+        self.dirty.store(true, Ordering::Release);
+        Ok(())
+    }
+
+    /// Returns the serialized keys of the best `limit` matches of `query`.
+    fn search(&self, query: &str, limit: usize) -> Result<Vec<Vec<u8>>, Error> {
+        if limit == 0 {
+            return Ok(Vec::new());
+        }
+        // Changes are committed lazily, so a batch of writes costs a single
+        // commit of the index.
+        if self.dirty.load(Ordering::Acquire) {
+            let mut writer = self.writer.lock().unwrap_or_else(PoisonError::into_inner);
+            if self.dirty.swap(false, Ordering::AcqRel) {
+                writer.commit()?;
+                self.reader.reload()?;
+            }
+        }
+        let query = QueryParser::for_index(&self.index, vec![self.text_field])
+            .parse_query(query)
+            .map_err(|_| Error::InvalidQuery)?;
+        let searcher = self.reader.searcher();
+        let mut keys = Vec::new();
+        for (_score, address) in searcher.search(&*query, &TopDocs::with_limit(limit))? {
+            let document = searcher.doc(address)?;
+            let key = document
+                .get_first(self.key_field)
+                .and_then(|key| key.as_bytes())
+                .ok_or(Error::CorruptMetadata)?;
+            keys.push(key.to_vec());
+        }
+        Ok(keys)
+    }
+}
+
+fn fill<T: SearchableCollection>(db: &Database, index: &SearchIndex) -> Result<(), Error> {
+    for entry in T::iter(db)? {
+        let (key, value) = entry?;
+        index.stage(&key.serialize(), Some(&value))?;
+    }
+    Ok(())
+}
+
+/// Indexes the values of every collection with a search index.
+pub(crate) fn fill_all(db: &Database) -> Result<(), Error> {
+    for index in db.hooks.search.values() {
+        (index.fill)(db, index)?;
+    }
+    Ok(())
+}
+
+impl DatabaseBuilder {
+    /// Maintains a search index of `T`, which has to be added to the builder
+    /// as well.
+    pub fn add_search_index<T: SearchableCollection + 'static>(mut self) -> Self {
+        // Creating an index in memory with a fixed configuration only fails
+        // on a bug.
+        let index = Arc::new(SearchIndex::new::<T>().expect("invalid search index configuration"));
+        self.hooks.search.insert(T::CF_NAME, index.clone());
+        self.hooks.push_post_commit(
+            T::CF_NAME,
+            Box::new(move |_db, key, _old, new| {
+                let new = new.map(|new| value::decode::<T>(key, new)).transpose()?;
+                index.stage(key, new.as_ref())
+            }),
+        );
+        self
+    }
+}
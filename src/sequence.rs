@@ -0,0 +1,72 @@
+use std::{
+    collections::HashMap,
+    sync::{Mutex, PoisonError},
+};
+
+use crate::{Collection, Database, Error};
+
+/// Number of ids reserved on disk at a time. Ids reserved but not handed out
+/// before the process exits are skipped.
+const RESERVATION: u64 = 1024;
+
+/// In-memory state of the id sequences, keyed by collection name.
+#[derive(Default)]
+pub(crate) struct Sequences(Mutex<HashMap<&'static str, Reservation>>);
+
+struct Reservation {
+    next: u64,
+    /// First id not covered by the reservation persisted on disk.
+    end: u64,
+}
+
+fn sequence_key(cf_name: &str) -> String {
+    format!("rkyvdb.sequence.{}", cf_name)
+}
+
+impl Database {
+    /// Allocates the next id for collection `C`.
+    ///
+    /// Ids start at 1 and are strictly increasing across restarts. They are
+    /// reserved in blocks, so ids can be skipped after a restart but never
+    /// handed out twice.
+    pub fn next_id<C: Collection>(&self) -> Result<u64, Error> {
+        self.allocate_id(C::CF_NAME)
+    }
+
+    /// Allocates the next id of the sequence of column family `cf_name`.
+    pub(crate) fn allocate_id(&self, cf_name: &'static str) -> Result<u64, Error> {
+        let mut sequences = self
+            .sequences
+            .0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let reservation = match sequences.get_mut(cf_name) {
+            Some(reservation) => reservation,
+            None => {
+                let next = match self.rocksdb.get_pinned(sequence_key(cf_name))? {
+                    Some(end) => u64::from_be_bytes(
+                        end.as_ref()
+                            .try_into()
+                            .map_err(|_| Error::CorruptMetadata)?,
+                    ),
+                    None => 1,
+                };
+                sequences
+                    .entry(cf_name)
+                    .or_insert(Reservation { next, end: next })
+            }
+        };
+        if reservation.next == reservation.end {
+            let end = reservation.end + RESERVATION;
+            let mut write_opts = rocksdb::WriteOptions::default();
+            // The reservation must survive a crash, or ids would be reused.
+            write_opts.set_sync(true);
+            self.rocksdb
+                .put_opt(sequence_key(cf_name), end.to_be_bytes(), &write_opts)?;
+            reservation.end = end;
+        }
+        let id = reservation.next;
+        reservation.next += 1;
+        Ok(id)
+    }
+}
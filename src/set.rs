@@ -0,0 +1,140 @@
+use std::marker::PhantomData;
+
+use crate::{iter, key, CfOptions, Database, DatabaseBuilder, Error, Key};
+
+/// Collection of sets of members, stored as one entry per member so that
+/// adding or removing a member writes only that member, however large the
+/// set.
+///
+/// Members are kept in the order of their serialized form, so a set of
+/// timestamps or sequence numbers doubles as an ordered list. The collection
+/// has to be registered with [`DatabaseBuilder::add_set_collection`].
+pub trait SetCollection {
+    type KeyType: Key;
+    type Member: Key;
+    const CF_NAME: &'static str;
+
+    /// Adds `member` to the set `key`. Adding a member twice is a no-op.
+    fn add_member<K: Into<Self::KeyType>>(
+        key: K,
+        member: &Self::Member,
+        db: &Database,
+    ) -> Result<(), Error> {
+        let member_key = member_key(&key.into().serialize(), &member.serialize());
+        db.rocksdb
+            .put_cf(&cf_handle(db, Self::CF_NAME)?, member_key, [])?;
+        Ok(())
+    }
+
+    fn remove_member<K: Into<Self::KeyType>>(
+        key: K,
+        member: &Self::Member,
+        db: &Database,
+    ) -> Result<(), Error> {
+        let member_key = member_key(&key.into().serialize(), &member.serialize());
+        db.rocksdb
+            .delete_cf(&cf_handle(db, Self::CF_NAME)?, member_key)?;
+        Ok(())
+    }
+
+    fn contains_member<K: Into<Self::KeyType>>(
+        key: K,
+        member: &Self::Member,
+        db: &Database,
+    ) -> Result<bool, Error> {
+        let member_key = member_key(&key.into().serialize(), &member.serialize());
+        Ok(db
+            .rocksdb
+            .get_pinned_cf(&cf_handle(db, Self::CF_NAME)?, member_key)?
+            .is_some())
+    }
+
+    /// Iterates over the members of the set `key` in order.
+    fn members<K: Into<Self::KeyType>>(key: K, db: &Database) -> Result<Members<'_, Self>, Error> {
+        let prefix = prefix(&key.into().serialize());
+        let mut raw = db.rocksdb.raw_iterator_cf_opt(
+            &cf_handle(db, Self::CF_NAME)?,
+            iter::prefix_read_options(&prefix, None),
+        );
+        raw.seek(&prefix);
+        Ok(Members {
+            raw,
+            prefix_len: prefix.len(),
+            done: false,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Removes every member of the set `key`.
+    fn clear<K: Into<Self::KeyType>>(key: K, db: &Database) -> Result<(), Error> {
+        let prefix = prefix(&key.into().serialize());
+        // Encoded keys end with a 0 byte, so they always have an upper bound.
+        let end = iter::prefix_upper_bound(&prefix).ok_or(Error::InvalidKey)?;
+        db.rocksdb
+            .delete_range_cf(&cf_handle(db, Self::CF_NAME)?, prefix, end)?;
+        Ok(())
+    }
+}
+
+impl DatabaseBuilder {
+    pub fn add_set_collection<S: SetCollection>(mut self) -> Self {
+        self.cf_descriptors.push((
+            S::CF_NAME.to_string(),
+            CfOptions::Default {
+                collection: |_| {},
+                configure: |_| {},
+                prefix_bloom: false,
+            },
+        ));
+        self
+    }
+}
+
+/// Iterator over the members of a set, created by
+/// [`SetCollection::members`].
+pub struct Members<'db, S: ?Sized> {
+    raw: rocksdb::DBRawIterator<'db>,
+    prefix_len: usize,
+    done: bool,
+    _marker: PhantomData<S>,
+}
+
+impl<'db, S: SetCollection + ?Sized> Iterator for Members<'db, S> {
+    type Item = Result<S::Member, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let member = match self.raw.key() {
+            Some(key) => S::Member::deserialize(&key[self.prefix_len..]).ok_or(Error::InvalidKey),
+            None => {
+                self.done = true;
+                return self.raw.status().err().map(|err| Err(err.into()));
+            }
+        };
+        self.raw.next();
+        Some(member)
+    }
+}
+
+/// Members of a set are stored under its encoded key, which no other encoded
+/// key starts with, followed by the serialized member.
+fn prefix(key: &[u8]) -> Vec<u8> {
+    let mut prefix = Vec::new();
+    key::encode_component(&mut prefix, key);
+    prefix
+}
+
+fn member_key(key: &[u8], member: &[u8]) -> Vec<u8> {
+    [&prefix(key)[..], member].concat()
+}
+
+fn cf_handle<'db>(
+    db: &'db Database,
+    cf_name: &str,
+) -> Result<rocksdb::ColumnFamilyRef<'db>, Error> {
+    db.rocksdb
+        .cf_handle(cf_name)
+        .ok_or(Error::CollectionNotRegistered)
+}
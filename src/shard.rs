@@ -0,0 +1,94 @@
+use crate::{hash, Collection, Database, DatabaseBuilder, Error, Key};
+
+/// Metadata key recording the position of a shard and the number of shards.
+const LAYOUT_KEY: &[u8] = b"rkyvdb.shard_layout";
+
+/// Database spread over several RocksDB instances, typically on separate
+/// disks, for write loads a single instance can't sustain.
+///
+/// Every entry lives in the shard picked by a hash of its serialized key,
+/// so the same shards must always be opened in the same order; each shard
+/// records its position and fails to open elsewhere. Operations on single
+/// keys are routed by the methods below, and [`ShardedDatabase::shard`]
+/// gives the [`Database`] to pass to any other [`Collection`] method for a
+/// key. Batches, transactions and iteration stay within one shard.
+pub struct ShardedDatabase {
+    shards: Vec<Database>,
+}
+
+impl ShardedDatabase {
+    /// Opens one shard at each of `paths`, with the options and collections
+    /// of the builder returned by `builder`. Fails with
+    /// [`Error::InvalidArgument`] if `paths` is empty.
+    pub fn open(paths: &[&str], builder: impl Fn() -> DatabaseBuilder) -> Result<Self, Error> {
+        if paths.is_empty() {
+            return Err(Error::InvalidArgument);
+        }
+        let mut shards = Vec::with_capacity(paths.len());
+        for (position, path) in paths.iter().enumerate() {
+            let shard = builder().open(path)?;
+            let layout = [
+                (position as u64).to_be_bytes(),
+                (paths.len() as u64).to_be_bytes(),
+            ]
+            .concat();
+            match shard.rocksdb.get_pinned(LAYOUT_KEY)? {
+                Some(stored) if *stored != *layout => return Err(Error::ShardMismatch),
+                Some(_) => {}
+                None => shard.rocksdb.put(LAYOUT_KEY, layout)?,
+            }
+            shards.push(shard);
+        }
+        Ok(ShardedDatabase { shards })
+    }
+
+    pub fn shards(&self) -> &[Database] {
+        &self.shards
+    }
+
+    /// Shard holding the entry of `T` under `key`.
+    pub fn shard<T: Collection>(&self, key: &T::KeyType) -> &Database {
+        let hash = hash::fnv1a(&key.serialize());
+        &self.shards[(hash % self.shards.len() as u64) as usize]
+    }
+
+    pub fn get<T: Collection>(&self, key: impl Into<T::KeyType>) -> Result<Option<T>, Error> {
+        let key = key.into();
+        let shard = self.shard::<T>(&key);
+        T::get(key, shard)
+    }
+
+    pub fn put<T: Collection>(&self, key: impl Into<T::KeyType>, value: &T) -> Result<(), Error> {
+        let key = key.into();
+        let shard = self.shard::<T>(&key);
+        T::put(key, value, shard)
+    }
+
+    pub fn delete<T: Collection>(&self, key: impl Into<T::KeyType>) -> Result<(), Error> {
+        let key = key.into();
+        let shard = self.shard::<T>(&key);
+        T::delete(key, shard)
+    }
+
+    /// Like [`Collection::modify`], in the shard of `key`.
+    pub fn modify<T: Collection, R>(
+        &self,
+        key: impl Into<T::KeyType>,
+        modifier: impl FnOnce(&mut Option<T>) -> R,
+    ) -> Result<R, Error> {
+        let key = key.into();
+        let shard = self.shard::<T>(&key);
+        T::modify(key, shard, modifier)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opening_no_shards_fails() {
+        let opened = ShardedDatabase::open(&[], Database::build);
+        assert!(matches!(opened, Err(Error::InvalidArgument)));
+    }
+}
@@ -0,0 +1,103 @@
+use std::ops::Range;
+
+use crate::{iter, value, Collection, Database, Error, Iter, Key};
+
+/// Consistent point-in-time view of the database.
+///
+/// Reads through a snapshot ignore all writes committed after it was taken,
+/// so several lookups and scans can observe the same state while writers keep
+/// going.
+pub struct Snapshot<'db> {
+    db: &'db Database,
+    snapshot: rocksdb::Snapshot<'db>,
+}
+
+impl Database {
+    pub fn snapshot(&self) -> Snapshot<'_> {
+        Snapshot {
+            db: self,
+            snapshot: self.rocksdb.snapshot(),
+        }
+    }
+}
+
+impl<'db> Snapshot<'db> {
+    pub fn get<T: Collection>(&self, key: impl Into<T::KeyType>) -> Result<Option<T>, Error> {
+        let cf = self.db.cf_handle::<T>()?;
+        let key: T::KeyType = key.into();
+        let key = key.serialize();
+        self.snapshot
+            .get_cf(&cf, &key)?
+            .map(|value| value::decode(&key, &value))
+            .transpose()
+    }
+
+    /// Iterates over the whole collection `T` in key order.
+    pub fn iter<T: Collection>(&self) -> Result<Iter<'_, T>, Error> {
+        let mut raw = self.snapshot.raw_iterator_cf(&self.db.cf_handle::<T>()?);
+        raw.seek_to_first();
+        Ok(Iter::new(self.db, raw))
+    }
+
+    /// Iterates over the whole collection `T` in reverse key order.
+    pub fn iter_rev<T: Collection>(&self) -> Result<Iter<'_, T>, Error> {
+        let mut raw = self.snapshot.raw_iterator_cf(&self.db.cf_handle::<T>()?);
+        raw.seek_to_last();
+        Ok(Iter::new_rev(self.db, raw))
+    }
+
+    /// Iterates in key order over the entries of `T` with keys greater than or
+    /// equal to `key`.
+    pub fn seek<T: Collection>(&self, key: impl Into<T::KeyType>) -> Result<Iter<'_, T>, Error> {
+        let key: T::KeyType = key.into();
+        let mut raw = self
+            .snapshot
+            .raw_iterator_cf_opt(&self.db.cf_handle::<T>()?, iter::total_order_read_options());
+        raw.seek(key.serialize());
+        Ok(Iter::new(self.db, raw))
+    }
+
+    /// Iterates in reverse key order over the entries of `T` with keys less
+    /// than or equal to `key`.
+    pub fn seek_for_prev<T: Collection>(
+        &self,
+        key: impl Into<T::KeyType>,
+    ) -> Result<Iter<'_, T>, Error> {
+        let key: T::KeyType = key.into();
+        let mut raw = self
+            .snapshot
+            .raw_iterator_cf_opt(&self.db.cf_handle::<T>()?, iter::total_order_read_options());
+        raw.seek_for_prev(key.serialize());
+        Ok(Iter::new_rev(self.db, raw))
+    }
+
+    /// Iterates over all entries of `T` whose key starts with `prefix`.
+    pub fn iter_prefix<T: Collection>(
+        &self,
+        prefix: impl Into<T::KeyType>,
+    ) -> Result<Iter<'_, T>, Error> {
+        let cf = self.db.cf_handle::<T>()?;
+        let prefix: T::KeyType = prefix.into();
+        let prefix = prefix.serialize();
+        let mut raw = self
+            .snapshot
+            .raw_iterator_cf_opt(&cf, iter::prefix_read_options(&prefix, T::PREFIX_LENGTH));
+        raw.seek(prefix);
+        Ok(Iter::new(self.db, raw))
+    }
+
+    /// Iterates over all entries of `T` with keys in `range.start..range.end`.
+    pub fn iter_range<T: Collection, K: Into<T::KeyType>>(
+        &self,
+        range: Range<K>,
+    ) -> Result<Iter<'_, T>, Error> {
+        let cf = self.db.cf_handle::<T>()?;
+        let start: T::KeyType = range.start.into();
+        let end: T::KeyType = range.end.into();
+        let mut opts = iter::total_order_read_options();
+        opts.set_iterate_upper_bound(end.serialize());
+        let mut raw = self.snapshot.raw_iterator_cf_opt(&cf, opts);
+        raw.seek(start.serialize());
+        Ok(Iter::new(self.db, raw))
+    }
+}
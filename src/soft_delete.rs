@@ -0,0 +1,133 @@
+use crate::{
+    iter, key, ttl,
+    write::{self, PendingWrite},
+    Collection, Database, Error, Key,
+};
+
+/// Number of purged values committed in one batch.
+const PURGE_BATCH: usize = 1024;
+
+/// Collection whose values can be deleted softly: a tombstone recording the
+/// deletion time marks the value as deleted, and the value itself is kept
+/// until [`SoftDeleteCollection::purge_deleted`] removes it.
+///
+/// Only the methods of this trait know about tombstones;
+/// [`Collection::get`], iteration and indexes still see deleted values, and
+/// writing a value doesn't restore it. Tombstones are kept in the default
+/// column family.
+pub trait SoftDeleteCollection: Collection {
+    /// Marks the value stored under `key` as deleted now. Does nothing if
+    /// there is no value or it is already deleted.
+    fn soft_delete<K: Into<Self::KeyType>>(key: K, db: &Database) -> Result<(), Error> {
+        let key: Self::KeyType = key.into();
+        let serialized_key = key.serialize();
+        let cf = db.cf_handle::<Self>()?;
+        let tombstone = tombstone_key(Self::CF_NAME, &serialized_key);
+        let _guard = db.locks.lock(Self::CF_NAME, &serialized_key);
+        if db.rocksdb.get_pinned(&tombstone)?.is_some()
+            || db.rocksdb.get_pinned_cf(&cf, &serialized_key)?.is_none()
+        {
+            return Ok(());
+        }
+        let deleted_at = ttl::now().to_be_bytes().to_vec();
+        write::commit(
+            db,
+            vec![PendingWrite::metadata(tombstone, Some(deleted_at))],
+        )
+    }
+
+    /// Removes the tombstone of `key`, returning whether there was one.
+    fn restore<K: Into<Self::KeyType>>(key: K, db: &Database) -> Result<bool, Error> {
+        let key: Self::KeyType = key.into();
+        let serialized_key = key.serialize();
+        let tombstone = tombstone_key(Self::CF_NAME, &serialized_key);
+        let _guard = db.locks.lock(Self::CF_NAME, &serialized_key);
+        if db.rocksdb.get_pinned(&tombstone)?.is_none() {
+            return Ok(false);
+        }
+        write::commit(db, vec![PendingWrite::metadata(tombstone, None)])?;
+        Ok(true)
+    }
+
+    /// Unix timestamp in seconds at which the value stored under `key` was
+    /// deleted, or `None` if it isn't deleted.
+    fn deleted_at<K: Into<Self::KeyType>>(key: K, db: &Database) -> Result<Option<u64>, Error> {
+        let key: Self::KeyType = key.into();
+        db.rocksdb
+            .get_pinned(tombstone_key(Self::CF_NAME, &key.serialize()))?
+            .map(|deleted_at| decode_time(&deleted_at))
+            .transpose()
+    }
+
+    /// Like [`Collection::get`], but returns `None` for deleted values.
+    fn get_live<K: Into<Self::KeyType>>(key: K, db: &Database) -> Result<Option<Self>, Error> {
+        let key: Self::KeyType = key.into();
+        let serialized_key = key.serialize();
+        if db
+            .rocksdb
+            .get_pinned(tombstone_key(Self::CF_NAME, &serialized_key))?
+            .is_some()
+        {
+            return Ok(None);
+        }
+        db.get_serialized(&serialized_key)
+    }
+
+    /// Removes the values deleted at or before the Unix timestamp `before`,
+    /// together with their tombstones, and returns how many were removed.
+    fn purge_deleted(before: u64, db: &Database) -> Result<u64, Error> {
+        db.cf_handle::<Self>()?;
+        let prefix = tombstone_prefix(Self::CF_NAME);
+        let mut raw = db
+            .rocksdb
+            .raw_iterator_opt(iter::prefix_read_options(&prefix, None));
+        raw.seek(&prefix);
+        let mut expired = Vec::new();
+        while let (Some(tombstone), Some(deleted_at)) = (raw.key(), raw.value()) {
+            if decode_time(deleted_at)? <= before {
+                expired.push(tombstone[prefix.len()..].to_vec());
+            }
+            raw.next();
+        }
+        raw.status()?;
+        let mut purged = 0;
+        for keys in expired.chunks(PURGE_BATCH) {
+            let _guard = db
+                .locks
+                .lock_many(keys.iter().map(|key| (Self::CF_NAME, key.as_slice())));
+            let mut writes = Vec::new();
+            for key in keys {
+                // Skip values restored since they were listed.
+                let tombstone = tombstone_key(Self::CF_NAME, key);
+                if db.rocksdb.get_pinned(&tombstone)?.is_none() {
+                    continue;
+                }
+                writes.push(PendingWrite::delete::<Self>(key));
+                writes.push(PendingWrite::metadata(tombstone, None));
+                purged += 1;
+            }
+            write::commit(db, writes)?;
+        }
+        Ok(purged)
+    }
+}
+
+/// Tombstones of a collection are keyed by a prefix naming the collection,
+/// followed by the serialized key.
+fn tombstone_prefix(cf_name: &str) -> Vec<u8> {
+    let mut prefix = b"rkyvdb.tombstone.".to_vec();
+    key::encode_component(&mut prefix, cf_name.as_bytes());
+    prefix
+}
+
+fn tombstone_key(cf_name: &str, key: &[u8]) -> Vec<u8> {
+    let mut tombstone = tombstone_prefix(cf_name);
+    tombstone.extend_from_slice(key);
+    tombstone
+}
+
+fn decode_time(bytes: &[u8]) -> Result<u64, Error> {
+    Ok(u64::from_be_bytes(
+        bytes.try_into().map_err(|_| Error::CorruptMetadata)?,
+    ))
+}
@@ -0,0 +1,126 @@
+use rocksdb::properties;
+
+use crate::{Collection, Database, DatabaseBuilder, Error};
+
+/// Counters of the whole database since it was opened.
+#[derive(Clone, Debug, Default)]
+pub struct Statistics {
+    pub block_cache_hits: u64,
+    pub block_cache_misses: u64,
+    pub memtable_hits: u64,
+    pub memtable_misses: u64,
+    pub keys_read: u64,
+    pub keys_written: u64,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+}
+
+impl Statistics {
+    /// Share of block reads served by the block cache, or `None` before the
+    /// first block read.
+    pub fn block_cache_hit_rate(&self) -> Option<f64> {
+        let reads = self.block_cache_hits + self.block_cache_misses;
+        (reads != 0).then(|| self.block_cache_hits as f64 / reads as f64)
+    }
+
+    /// Parses the tickers of the `rocksdb.options-statistics` property, lines
+    /// like `rocksdb.block.cache.hit COUNT : 42`.
+    fn parse(dump: &str) -> Self {
+        let mut statistics = Statistics::default();
+        for line in dump.lines() {
+            let Some((name, count)) = line.split_once(" COUNT : ") else {
+                continue;
+            };
+            let field = match name {
+                "rocksdb.block.cache.hit" => &mut statistics.block_cache_hits,
+                "rocksdb.block.cache.miss" => &mut statistics.block_cache_misses,
+                "rocksdb.memtable.hit" => &mut statistics.memtable_hits,
+                "rocksdb.memtable.miss" => &mut statistics.memtable_misses,
+                "rocksdb.number.keys.read" => &mut statistics.keys_read,
+                "rocksdb.number.keys.written" => &mut statistics.keys_written,
+                "rocksdb.bytes.read" => &mut statistics.bytes_read,
+                "rocksdb.bytes.written" => &mut statistics.bytes_written,
+                _ => continue,
+            };
+            *field = count.trim().parse().unwrap_or(0);
+        }
+        statistics
+    }
+}
+
+/// Size estimates of one collection, not counting its indexes.
+#[derive(Clone, Debug, Default)]
+pub struct CollectionProperties {
+    pub estimated_keys: u64,
+    /// Estimated size of the live data in SST files.
+    pub live_data_size: u64,
+    /// Size of the SST files of the current version.
+    pub live_sst_files_size: u64,
+    /// Size of all SST files, including those kept by snapshots and
+    /// iterators.
+    pub total_sst_files_size: u64,
+    pub memtables_size: u64,
+    /// Bytes that compactions still have to rewrite.
+    pub pending_compaction_bytes: u64,
+}
+
+/// Collection registered with the builder, listed by
+/// [`Database::collections`].
+#[derive(Clone, Debug)]
+pub struct CollectionInfo {
+    pub name: &'static str,
+    /// Name of the key type, as given by [`std::any::type_name`].
+    pub key_type: &'static str,
+    pub indexes: &'static [&'static str],
+    /// [`Collection::SCHEMA_VERSION`] the collection is registered with.
+    pub schema_version: u32,
+    /// Schema version of the stored values, older than `schema_version`
+    /// until [`Database::migrate`] has run.
+    pub stored_schema_version: u32,
+    pub properties: CollectionProperties,
+}
+
+impl DatabaseBuilder {
+    /// Collects the counters returned by [`Database::statistics`], at a small
+    /// cost on every operation.
+    pub fn enable_statistics(mut self) -> Self {
+        self.opts.enable_statistics();
+        self
+    }
+}
+
+impl Database {
+    /// Returns the counters collected since the database was opened, or
+    /// `None` unless [`DatabaseBuilder::enable_statistics`] was set.
+    pub fn statistics(&self) -> Result<Option<Statistics>, Error> {
+        Ok(self
+            .rocksdb
+            .property_value(properties::OPTIONS_STATISTICS)?
+            .map(|dump| Statistics::parse(&dump)))
+    }
+
+    /// Describes every collection registered with the builder, for admin
+    /// tools and health checks. Collections added with other builder methods,
+    /// like queues or counters, aren't listed.
+    pub fn collections(&self) -> Result<Vec<CollectionInfo>, Error> {
+        self.schemas.infos(self)
+    }
+}
+
+pub(crate) fn property<T: Collection>(db: &Database, name: &str) -> Result<Option<u64>, Error> {
+    Ok(db
+        .rocksdb
+        .property_int_value_cf(&db.cf_handle::<T>()?, name)?)
+}
+
+pub(crate) fn properties<T: Collection>(db: &Database) -> Result<CollectionProperties, Error> {
+    let property = |name| Ok::<_, Error>(property::<T>(db, name)?.unwrap_or(0));
+    Ok(CollectionProperties {
+        estimated_keys: property(properties::ESTIMATE_NUM_KEYS)?,
+        live_data_size: property(properties::ESTIMATE_LIVE_DATA_SIZE)?,
+        live_sst_files_size: property(properties::LIVE_SST_FILES_SIZE)?,
+        total_sst_files_size: property(properties::TOTAL_SST_FILES_SIZE)?,
+        memtables_size: property(properties::CUR_SIZE_ALL_MEM_TABLES)?,
+        pending_compaction_bytes: property(properties::ESTIMATE_PENDING_COMPACTION_BYTES)?,
+    })
+}
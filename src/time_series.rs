@@ -0,0 +1,231 @@
+use std::{marker::PhantomData, ops::Range};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{iter, key, CfOptions, Database, DatabaseBuilder, Error, Key};
+
+/// Collection of timestamped samples grouped in series, such as the readings
+/// of each sensor.
+///
+/// Samples are keyed by series and timestamp, so the samples of a series in
+/// a time range are read in one seek. Timestamps are `u64` in a unit of the
+/// collection's choosing, and appending a sample at an existing timestamp
+/// replaces it. The collection has to be registered with
+/// [`DatabaseBuilder::add_time_series`].
+pub trait TimeSeriesCollection: Serialize + DeserializeOwned + Sized {
+    type SeriesId: Key;
+    const CF_NAME: &'static str;
+
+    /// Stores `value` as the sample of `series` at `timestamp`.
+    fn append<S: Into<Self::SeriesId>>(
+        series: S,
+        timestamp: u64,
+        value: &Self,
+        db: &Database,
+    ) -> Result<(), Error> {
+        let value = rmp_serde::encode::to_vec(value).map_err(Error::RmpEncode)?;
+        let key = sample_key(&prefix(&series.into().serialize()), timestamp);
+        db.rocksdb
+            .put_cf(&cf_handle(db, Self::CF_NAME)?, key, value)?;
+        Ok(())
+    }
+
+    /// Iterates over the samples of `series` with a timestamp in `range`,
+    /// oldest first.
+    fn range<S: Into<Self::SeriesId>>(
+        series: S,
+        range: Range<u64>,
+        db: &Database,
+    ) -> Result<Samples<'_, Self>, Error> {
+        let prefix = prefix(&series.into().serialize());
+        let mut opts = iter::total_order_read_options();
+        opts.set_iterate_upper_bound(sample_key(&prefix, range.end));
+        let mut raw = db
+            .rocksdb
+            .raw_iterator_cf_opt(&cf_handle(db, Self::CF_NAME)?, opts);
+        raw.seek(sample_key(&prefix, range.start));
+        Ok(Samples {
+            raw,
+            prefix_len: prefix.len(),
+            done: false,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Removes the samples of `series` older than `timestamp`.
+    fn delete_before<S: Into<Self::SeriesId>>(
+        series: S,
+        timestamp: u64,
+        db: &Database,
+    ) -> Result<(), Error> {
+        let prefix = prefix(&series.into().serialize());
+        db.rocksdb.delete_range_cf(
+            &cf_handle(db, Self::CF_NAME)?,
+            sample_key(&prefix, 0),
+            sample_key(&prefix, timestamp),
+        )?;
+        Ok(())
+    }
+
+    /// Folds the samples of `series` in `range` into buckets of `bucket`
+    /// timestamps each, aligned on multiples of `bucket`, and returns the
+    /// start and aggregate of every bucket with samples. Buckets start from
+    /// `init` and take their samples in order through `fold`, e.g. to sum or
+    /// average them.
+    ///
+    /// Fails with [`Error::InvalidArgument`] if `bucket` is 0.
+    fn rollup<S: Into<Self::SeriesId>, A>(
+        series: S,
+        range: Range<u64>,
+        bucket: u64,
+        db: &Database,
+        mut init: impl FnMut() -> A,
+        mut fold: impl FnMut(&mut A, Self),
+    ) -> Result<Vec<(u64, A)>, Error> {
+        if bucket == 0 {
+            return Err(Error::InvalidArgument);
+        }
+        let mut buckets: Vec<(u64, A)> = Vec::new();
+        for sample in Self::range(series, range, db)? {
+            let (timestamp, value) = sample?;
+            let start = timestamp - timestamp % bucket;
+            match buckets.last_mut() {
+                Some((last, aggregate)) if *last == start => fold(aggregate, value),
+                _ => {
+                    let mut aggregate = init();
+                    fold(&mut aggregate, value);
+                    buckets.push((start, aggregate));
+                }
+            }
+        }
+        Ok(buckets)
+    }
+
+    /// Keeps the last sample of `series` of every bucket of `bucket`
+    /// timestamps in `range`, as [`TimeSeriesCollection::rollup`] groups
+    /// them, returned with the start of its bucket.
+    fn downsample<S: Into<Self::SeriesId>>(
+        series: S,
+        range: Range<u64>,
+        bucket: u64,
+        db: &Database,
+    ) -> Result<Vec<(u64, Self)>, Error> {
+        let buckets = Self::rollup(
+            series,
+            range,
+            bucket,
+            db,
+            || None,
+            |last, value| *last = Some(value),
+        )?;
+        Ok(buckets
+            .into_iter()
+            .filter_map(|(start, last)| Some((start, last?)))
+            .collect())
+    }
+}
+
+impl DatabaseBuilder {
+    pub fn add_time_series<T: TimeSeriesCollection>(mut self) -> Self {
+        self.cf_descriptors.push((
+            T::CF_NAME.to_string(),
+            CfOptions::Default {
+                collection: |_| {},
+                configure: |_| {},
+                prefix_bloom: false,
+            },
+        ));
+        self
+    }
+}
+
+/// Iterator over the samples of a series, created by
+/// [`TimeSeriesCollection::range`]. Yields timestamps with their values.
+pub struct Samples<'db, T> {
+    raw: rocksdb::DBRawIterator<'db>,
+    prefix_len: usize,
+    done: bool,
+    _marker: PhantomData<T>,
+}
+
+impl<'db, T: TimeSeriesCollection> Iterator for Samples<'db, T> {
+    type Item = Result<(u64, T), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let (key, value) = match (self.raw.key(), self.raw.value()) {
+            (Some(key), Some(value)) => (key, value),
+            _ => {
+                self.done = true;
+                return self.raw.status().err().map(|err| Err(err.into()));
+            }
+        };
+        let sample = decode_timestamp(&key[self.prefix_len..]).and_then(|timestamp| {
+            let value = rmp_serde::decode::from_slice(value).map_err(Error::RmpDecode)?;
+            Ok((timestamp, value))
+        });
+        self.raw.next();
+        Some(sample)
+    }
+}
+
+/// Samples of a series are stored under its encoded id, which no other
+/// encoded id starts with, followed by the timestamp as a big-endian `u64`.
+fn prefix(series: &[u8]) -> Vec<u8> {
+    let mut prefix = Vec::new();
+    key::encode_component(&mut prefix, series);
+    prefix
+}
+
+fn sample_key(prefix: &[u8], timestamp: u64) -> Vec<u8> {
+    [prefix, &timestamp.to_be_bytes()].concat()
+}
+
+fn decode_timestamp(bytes: &[u8]) -> Result<u64, Error> {
+    Ok(u64::from_be_bytes(
+        bytes.try_into().map_err(|_| Error::InvalidKey)?,
+    ))
+}
+
+fn cf_handle<'db>(
+    db: &'db Database,
+    cf_name: &str,
+) -> Result<rocksdb::ColumnFamilyRef<'db>, Error> {
+    db.rocksdb
+        .cf_handle(cf_name)
+        .ok_or(Error::CollectionNotRegistered)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize)]
+    struct Reading(u32);
+
+    impl TimeSeriesCollection for Reading {
+        type SeriesId = u32;
+        const CF_NAME: &'static str = "readings";
+    }
+
+    #[test]
+    fn samples_roll_up_into_aligned_buckets() {
+        let db = Database::build()
+            .add_time_series::<Reading>()
+            .open_temp()
+            .unwrap();
+        for timestamp in [3, 9, 10, 25] {
+            Reading::append(1u32, timestamp, &Reading(1), &db).unwrap();
+        }
+        let sums = Reading::rollup(1u32, 0..30, 10, &db, || 0, |sum, r| *sum += r.0).unwrap();
+        assert_eq!(sums, [(0, 2), (10, 1), (20, 1)]);
+        assert!(matches!(
+            Reading::rollup(1u32, 0..30, 0, &db, || 0, |sum, r| *sum += r.0),
+            Err(Error::InvalidArgument)
+        ));
+    }
+}
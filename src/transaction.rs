@@ -0,0 +1,163 @@
+use std::collections::{hash_map::Entry, HashMap};
+
+use crate::{
+    value,
+    write::{self, PendingWrite},
+    Collection, Database, Error, Key, Retry,
+};
+
+/// Optimistic transaction spanning any number of collections.
+///
+/// Writes are staged until [`Transaction::commit`]. Reads see the writes
+/// staged so far, and otherwise go to the database and remember the value
+/// they observed. On commit every key that
+/// was read is checked again, and if any of them changed in the meantime the
+/// transaction fails with [`Error::TransactionConflict`] without writing
+/// anything.
+///
+/// Writes staged after a [`Transaction::savepoint`] can be undone with
+/// [`Transaction::rollback_to_savepoint`], leaving the rest of the
+/// transaction intact.
+pub struct Transaction<'db> {
+    db: &'db Database,
+    reads: HashMap<(&'static str, Vec<u8>), Option<Vec<u8>>>,
+    writes: Vec<PendingWrite>,
+    /// Number of writes staged when each savepoint was set, innermost last.
+    savepoints: Vec<usize>,
+}
+
+impl Database {
+    pub fn transaction(&self) -> Transaction<'_> {
+        Transaction {
+            db: self,
+            reads: HashMap::new(),
+            writes: Vec::new(),
+            savepoints: Vec::new(),
+        }
+    }
+
+    /// Runs `f` in a transaction and commits it, running `f` again in a new
+    /// transaction whenever the commit conflicts with a concurrent write, as
+    /// often as `retry` allows, before failing with
+    /// [`Error::TransactionConflict`]. Returns what the successful run of `f`
+    /// returned; an error from `f` aborts the transaction without writing
+    /// anything.
+    ///
+    /// `f` may run several times, so it shouldn't have side effects outside
+    /// of the transaction.
+    pub fn atomically<R>(
+        &self,
+        retry: Retry,
+        mut f: impl FnMut(&mut Transaction<'_>) -> Result<R, Error>,
+    ) -> Result<R, Error> {
+        let mut attempt = 0;
+        loop {
+            let mut transaction = self.transaction();
+            let result = f(&mut transaction)?;
+            match transaction.commit() {
+                Ok(()) => return Ok(result),
+                Err(Error::TransactionConflict) if retry.wait(attempt) => attempt += 1,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+impl<'db> Transaction<'db> {
+    pub fn get<T: Collection>(&mut self, key: impl Into<T::KeyType>) -> Result<Option<T>, Error> {
+        self.get_serialized(&key.into().serialize())
+    }
+
+    pub fn put<T: Collection>(
+        &mut self,
+        key: impl Into<T::KeyType>,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.put_serialized(&key.into().serialize(), value)
+    }
+
+    pub fn delete<T: Collection>(&mut self, key: impl Into<T::KeyType>) -> Result<(), Error> {
+        self.writes
+            .push(PendingWrite::delete::<T>(&key.into().serialize()));
+        Ok(())
+    }
+
+    /// Updates the value of `T` stored under `key` like [`Collection::modify`].
+    pub fn modify<T: Collection, R>(
+        &mut self,
+        key: impl Into<T::KeyType>,
+        modifier: impl FnOnce(&mut Option<T>) -> R,
+    ) -> Result<R, Error> {
+        let key: T::KeyType = key.into();
+        let serialized_key = key.serialize();
+        let mut value = self.get_serialized(&serialized_key)?;
+        let result = modifier(&mut value);
+        match value {
+            Some(value) => self.put_serialized(&serialized_key, &value)?,
+            None => self.writes.push(PendingWrite::delete::<T>(&serialized_key)),
+        }
+        Ok(result)
+    }
+
+    /// Marks the writes staged so far, so that the ones staged later can be
+    /// undone. Savepoints nest: each rollback returns to the latest one.
+    pub fn savepoint(&mut self) {
+        self.savepoints.push(self.writes.len());
+    }
+
+    /// Undoes the writes staged since the latest savepoint and removes it.
+    ///
+    /// Values read since then are still checked for conflicts on commit,
+    /// since what they were used for can't be known.
+    pub fn rollback_to_savepoint(&mut self) -> Result<(), Error> {
+        let len = self.savepoints.pop().ok_or(Error::NoSavepoint)?;
+        self.writes.truncate(len);
+        Ok(())
+    }
+
+    pub fn commit(self) -> Result<(), Error> {
+        let _guard = self.db.locks.lock_many(
+            self.reads
+                .keys()
+                .map(|(cf_name, key)| (*cf_name, key.as_slice()))
+                .chain(self.writes.iter().map(PendingWrite::lock_key)),
+        );
+        for ((cf_name, key), expected) in &self.reads {
+            let cf = self
+                .db
+                .rocksdb
+                .cf_handle(cf_name)
+                .ok_or(Error::CollectionNotRegistered)?;
+            let current = self.db.rocksdb.get_pinned_cf(&cf, key)?;
+            if current.as_deref() != expected.as_deref() {
+                return Err(Error::TransactionConflict);
+            }
+        }
+        write::commit(self.db, self.writes)
+    }
+
+    fn get_serialized<T: Collection>(&mut self, key: &[u8]) -> Result<Option<T>, Error> {
+        if let Some(staged) = write::staged(&self.writes, T::CF_NAME, key) {
+            return staged.map(|staged| value::decode(key, staged)).transpose();
+        }
+        let cf = self.db.cf_handle::<T>()?;
+        let value = match self.reads.entry((T::CF_NAME, key.to_vec())) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(
+                self.db
+                    .rocksdb
+                    .get_pinned_cf(&cf, key)?
+                    .map(|value| value.to_vec()),
+            ),
+        };
+        value
+            .as_deref()
+            .map(|value| value::decode(key, value))
+            .transpose()
+    }
+
+    fn put_serialized<T: Collection>(&mut self, key: &[u8], value: &T) -> Result<(), Error> {
+        self.writes.push(PendingWrite::put(key, value)?);
+        Ok(())
+    }
+}
@@ -0,0 +1,45 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{value, Collection, DatabaseBuilder, Options};
+
+/// Collection whose values expire.
+///
+/// Expired values are dropped by a compaction filter, so they can still be
+/// returned by reads until their data gets compacted; check
+/// [`ExpiringCollection::is_expired`] where that matters. The collection has to
+/// be registered with [`DatabaseBuilder::add_expiring_collection`].
+pub trait ExpiringCollection: Collection + 'static {
+    /// Unix timestamp in seconds after which the value is stale, or `None` if
+    /// it never expires.
+    fn expires_at(&self) -> Option<u64>;
+
+    fn is_expired(&self) -> bool {
+        self.expires_at()
+            .is_some_and(|expires_at| expires_at <= now())
+    }
+}
+
+impl DatabaseBuilder {
+    /// Adds a collection like [`DatabaseBuilder::add_collection`] and installs
+    /// the compaction filter dropping its expired values.
+    pub fn add_expiring_collection<T: ExpiringCollection>(self) -> Self {
+        self.add_collection_with::<T>(configure_cf::<T>)
+    }
+}
+
+fn configure_cf<T: ExpiringCollection>(opts: &mut Options) {
+    T::cf_options(opts);
+    opts.set_compaction_filter(T::CF_NAME, |_level: u32, key: &[u8], value: &[u8]| {
+        match value::decode::<T>(key, value) {
+            Ok(value) if value.is_expired() => rocksdb::CompactionDecision::Remove,
+            // Never drop what can't be decoded, it is not ours to judge.
+            _ => rocksdb::CompactionDecision::Keep,
+        }
+    });
+}
+
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs())
+}
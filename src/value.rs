@@ -0,0 +1,267 @@
+//! Encoding of stored values.
+//!
+//! Values of collections with [`Collection::VERSION`] 0 are plain MessagePack.
+//! Others are stamped with their version: a `0xc1` byte, which never starts
+//! a MessagePack value, followed by the version as a big-endian `u32` and the
+//! MessagePack value. Values of collections with encryption are then
+//! encrypted as a whole, bound to the column family and key they are stored
+//! under.
+
+use std::borrow::Cow;
+
+use crate::{Collection, Error};
+
+const VERSION_MARKER: u8 = 0xc1;
+
+/// Encodes `value` to be stored under `key` of `T`.
+pub(crate) fn encode<T: Collection>(key: &[u8], value: &T) -> Result<Vec<u8>, Error> {
+    encode_in(T::CF_NAME, key, value)
+}
+
+/// Like [`encode`], for a value of `T` stored in the column family `cf_name`,
+/// such as a dynamic collection.
+pub(crate) fn encode_in<T: Collection>(
+    cf_name: &str,
+    key: &[u8],
+    value: &T,
+) -> Result<Vec<u8>, Error> {
+    seal::<T>(cf_name, key, serialize(value)?)
+}
+
+/// Decodes the value stored under `key` of `T`.
+pub(crate) fn decode<T: Collection>(key: &[u8], bytes: &[u8]) -> Result<T, Error> {
+    decode_in(T::CF_NAME, key, bytes)
+}
+
+/// Like [`decode`], for a value of `T` stored in the column family `cf_name`.
+pub(crate) fn decode_in<T: Collection>(
+    cf_name: &str,
+    key: &[u8],
+    bytes: &[u8],
+) -> Result<T, Error> {
+    let bytes = unseal::<T>(cf_name, key, bytes)?;
+    let (version, body) = split_version(&bytes);
+    deserialize(version, body)
+}
+
+/// Encodes `value` without encrypting it. Unlike encrypted values, the result
+/// is the same for equal values.
+pub(crate) fn serialize<T: Collection>(value: &T) -> Result<Vec<u8>, Error> {
+    let mut bytes = Vec::new();
+    if T::VERSION != 0 {
+        bytes.push(VERSION_MARKER);
+        bytes.extend_from_slice(&T::VERSION.to_be_bytes());
+    }
+    rmp_serde::encode::write(&mut bytes, value).map_err(Error::RmpEncode)?;
+    Ok(bytes)
+}
+
+/// Splits a value in the form returned by [`serialize`] into its version and
+/// its MessagePack value.
+pub(crate) fn split_version(bytes: &[u8]) -> (u32, &[u8]) {
+    match *bytes {
+        [VERSION_MARKER, a, b, c, d, ref body @ ..] => (u32::from_be_bytes([a, b, c, d]), body),
+        _ => (0, bytes),
+    }
+}
+
+/// Decodes the MessagePack value `body` stored by `version` of `T`,
+/// upgrading it if it is older than [`Collection::VERSION`].
+pub(crate) fn deserialize<T: Collection>(version: u32, body: &[u8]) -> Result<T, Error> {
+    if version == T::VERSION {
+        rmp_serde::decode::from_slice(body).map_err(Error::RmpDecode)
+    } else if version < T::VERSION {
+        T::upgrade(body, version)
+    } else {
+        Err(Error::SchemaTooNew)
+    }
+}
+
+/// Whether `stored`, the bytes stored under `key` of the column family
+/// `cf_name`, hold `value`. Values are compared before encryption, and a
+/// stored value of an older [`Collection::VERSION`] as it would be stored
+/// now.
+pub(crate) fn matches<T: Collection>(
+    cf_name: &str,
+    key: &[u8],
+    stored: Option<&[u8]>,
+    value: Option<&T>,
+) -> Result<bool, Error> {
+    let (stored, value) = match (stored, value) {
+        (Some(stored), Some(value)) => (stored, value),
+        (stored, value) => return Ok(stored.is_none() && value.is_none()),
+    };
+    let stored = unseal::<T>(cf_name, key, stored)?;
+    let value = serialize(value)?;
+    match split_version(&stored) {
+        (version, _) if version == T::VERSION => Ok(*stored == *value),
+        (version, body) => Ok(serialize(&deserialize::<T>(version, body)?)? == value),
+    }
+}
+
+#[cfg(feature = "encryption")]
+fn seal<T: Collection>(cf_name: &str, key: &[u8], bytes: Vec<u8>) -> Result<Vec<u8>, Error> {
+    match T::encryption() {
+        Some(encryption) => encryption.encrypt(&bytes, cf_name, key),
+        None => Ok(bytes),
+    }
+}
+
+#[cfg(not(feature = "encryption"))]
+#[allow(clippy::extra_unused_type_parameters)]
+fn seal<T: Collection>(_cf_name: &str, _key: &[u8], bytes: Vec<u8>) -> Result<Vec<u8>, Error> {
+    Ok(bytes)
+}
+
+/// Decrypts a value stored under `key` of the column family `cf_name` into
+/// the form returned by [`serialize`].
+#[cfg(feature = "encryption")]
+pub(crate) fn unseal<'a, T: Collection>(
+    cf_name: &str,
+    key: &[u8],
+    bytes: &'a [u8],
+) -> Result<Cow<'a, [u8]>, Error> {
+    match T::encryption() {
+        Some(encryption) => encryption.decrypt(bytes, cf_name, key).map(Cow::Owned),
+        None => Ok(Cow::Borrowed(bytes)),
+    }
+}
+
+/// Decrypts a value stored under `key` of the column family `cf_name` into
+/// the form returned by [`serialize`].
+#[cfg(not(feature = "encryption"))]
+#[allow(clippy::extra_unused_type_parameters)]
+pub(crate) fn unseal<'a, T: Collection>(
+    _cf_name: &str,
+    _key: &[u8],
+    bytes: &'a [u8],
+) -> Result<Cow<'a, [u8]>, Error> {
+    Ok(Cow::Borrowed(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::{temp::TempDir, Database, Key};
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct V0(String);
+
+    impl Collection for V0 {
+        type KeyType = ();
+        const CF_NAME: &'static str = "values";
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct V2 {
+        name: String,
+        upgraded_from: u32,
+    }
+
+    impl Collection for V2 {
+        type KeyType = ();
+        const CF_NAME: &'static str = "values";
+        const VERSION: u32 = 2;
+
+        fn upgrade(old: &[u8], version: u32) -> Result<Self, Error> {
+            Ok(V2 {
+                name: rmp_serde::decode::from_slice::<V0>(old)?.0,
+                upgraded_from: version,
+            })
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct V3(String);
+
+    impl Collection for V3 {
+        type KeyType = ();
+        const CF_NAME: &'static str = "values";
+        const VERSION: u32 = 3;
+    }
+
+    #[test]
+    fn versions_are_stamped_only_when_set() {
+        let v0 = encode(b"k", &V0("a".to_string())).unwrap();
+        assert_eq!(split_version(&v0), (0, &v0[..]));
+        let v2 = V2 {
+            name: "a".to_string(),
+            upgraded_from: 2,
+        };
+        let bytes = encode(b"k", &v2).unwrap();
+        assert_eq!(bytes[..5], [VERSION_MARKER, 0, 0, 0, 2]);
+        assert_eq!(split_version(&bytes).0, 2);
+        assert_eq!(decode::<V2>(b"k", &bytes).unwrap(), v2);
+    }
+
+    #[test]
+    fn older_values_are_upgraded_on_read() {
+        let v0 = encode(b"k", &V0("a".to_string())).unwrap();
+        let v2 = decode::<V2>(b"k", &v0).unwrap();
+        assert_eq!((v2.name.as_str(), v2.upgraded_from), ("a", 0));
+    }
+
+    #[test]
+    fn newer_values_are_rejected() {
+        let v3 = encode(b"k", &V3("a".to_string())).unwrap();
+        assert!(matches!(decode::<V2>(b"k", &v3), Err(Error::SchemaTooNew)));
+    }
+
+    #[test]
+    fn older_values_match_their_upgrade() {
+        let v0 = encode(b"k", &V0("a".to_string())).unwrap();
+        let upgraded = V2 {
+            name: "a".to_string(),
+            upgraded_from: 0,
+        };
+        assert!(matches("values", b"k", Some(&v0), Some(&upgraded)).unwrap());
+        let other = V2 {
+            upgraded_from: 2,
+            ..upgraded
+        };
+        assert!(!matches("values", b"k", Some(&v0), Some(&other)).unwrap());
+        assert!(!matches("values", b"k", None, Some(&other)).unwrap());
+        assert!(matches::<V2>("values", b"k", None, None).unwrap());
+    }
+
+    #[test]
+    fn older_values_can_be_swapped() {
+        let dir = TempDir::new();
+        let path = dir.path().to_str().unwrap();
+        {
+            let db = Database::open_with_collections::<(V0,)>(path).unwrap();
+            V0::put((), &V0("a".to_string()), &db).unwrap();
+        }
+        let db = Database::open_with_collections::<(V2,)>(path).unwrap();
+        let upgraded = V2 {
+            name: "a".to_string(),
+            upgraded_from: 0,
+        };
+        let new = V2 {
+            name: "b".to_string(),
+            upgraded_from: 2,
+        };
+        assert!(V2::compare_and_swap((), Some(&upgraded), Some(&new), &db).unwrap());
+        assert_eq!(V2::get((), &db).unwrap(), Some(new));
+    }
+
+    #[test]
+    fn unchanged_older_values_are_not_rewritten() {
+        let dir = TempDir::new();
+        let path = dir.path().to_str().unwrap();
+        {
+            let db = Database::open_with_collections::<(V0,)>(path).unwrap();
+            V0::put((), &V0("a".to_string()), &db).unwrap();
+        }
+        let db = Database::open_with_collections::<(V2,)>(path).unwrap();
+        V2::modify((), &db, |_| ()).unwrap();
+        let stored = db
+            .rocksdb
+            .get_cf(&db.cf_handle::<V2>().unwrap(), Key::serialize(&()))
+            .unwrap()
+            .unwrap();
+        assert_eq!(split_version(&stored).0, 0);
+    }
+}
@@ -0,0 +1,180 @@
+use std::marker::PhantomData;
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{iter, key, CfOptions, Database, DatabaseBuilder, Error, Key};
+
+/// Collection keeping every version of its values.
+///
+/// Each write of a key adds a version numbered one more than the previous
+/// one, starting at 1, and deleting a key adds a version without a value, so
+/// the whole history of a key stays readable with
+/// [`VersionedCollection::history`]. Nothing is ever removed. The collection
+/// has to be registered with [`DatabaseBuilder::add_versioned_collection`].
+pub trait VersionedCollection: Serialize + DeserializeOwned + Sized {
+    type KeyType: Key;
+    const CF_NAME: &'static str;
+
+    /// Stores `value` as the new version of `key` and returns its number.
+    fn put<K: Into<Self::KeyType>>(key: K, value: &Self, db: &Database) -> Result<u64, Error> {
+        let value = rmp_serde::encode::to_vec(value).map_err(Error::RmpEncode)?;
+        write_version(db, Self::CF_NAME, &key.into().serialize(), &value)
+    }
+
+    /// Adds a version of `key` without a value and returns its number.
+    fn delete<K: Into<Self::KeyType>>(key: K, db: &Database) -> Result<u64, Error> {
+        // MessagePack values are never empty.
+        write_version(db, Self::CF_NAME, &key.into().serialize(), &[])
+    }
+
+    /// Returns the number and value of the latest version of `key`, unless
+    /// there is none or it was a delete.
+    fn get_latest<K: Into<Self::KeyType>>(
+        key: K,
+        db: &Database,
+    ) -> Result<Option<(u64, Self)>, Error> {
+        Self::get_at(key, u64::MAX, db)
+    }
+
+    /// Returns the number and value of the last version of `key` up to
+    /// `version`, unless there is none or it was a delete.
+    fn get_at<K: Into<Self::KeyType>>(
+        key: K,
+        version: u64,
+        db: &Database,
+    ) -> Result<Option<(u64, Self)>, Error> {
+        let prefix = prefix(&key.into().serialize());
+        match last_version(db, Self::CF_NAME, &prefix, version)? {
+            Some((version, Some(value))) => Ok(Some((version, decode(&value)?))),
+            _ => Ok(None),
+        }
+    }
+
+    /// Iterates over the versions of `key`, oldest first. Deletes are yielded
+    /// as `None`.
+    fn history<K: Into<Self::KeyType>>(key: K, db: &Database) -> Result<History<'_, Self>, Error> {
+        let prefix = prefix(&key.into().serialize());
+        let mut raw = db.rocksdb.raw_iterator_cf_opt(
+            &cf_handle(db, Self::CF_NAME)?,
+            iter::prefix_read_options(&prefix, None),
+        );
+        raw.seek(&prefix);
+        Ok(History {
+            raw,
+            prefix_len: prefix.len(),
+            done: false,
+            _marker: PhantomData,
+        })
+    }
+}
+
+impl DatabaseBuilder {
+    pub fn add_versioned_collection<V: VersionedCollection>(mut self) -> Self {
+        self.cf_descriptors.push((
+            V::CF_NAME.to_string(),
+            CfOptions::Default {
+                collection: |_| {},
+                configure: |_| {},
+                prefix_bloom: false,
+            },
+        ));
+        self
+    }
+}
+
+/// Iterator over the versions of a key of a [`VersionedCollection`], created
+/// by [`VersionedCollection::history`].
+pub struct History<'db, V> {
+    raw: rocksdb::DBRawIterator<'db>,
+    prefix_len: usize,
+    done: bool,
+    _marker: PhantomData<V>,
+}
+
+impl<'db, V: VersionedCollection> Iterator for History<'db, V> {
+    type Item = Result<(u64, Option<V>), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let (key, value) = match (self.raw.key(), self.raw.value()) {
+            (Some(key), Some(value)) => (key, value),
+            _ => {
+                self.done = true;
+                return self.raw.status().err().map(|err| Err(err.into()));
+            }
+        };
+        let item = decode_version(&key[self.prefix_len..]).and_then(|version| {
+            let value = (!value.is_empty()).then(|| decode(value)).transpose()?;
+            Ok((version, value))
+        });
+        self.raw.next();
+        Some(item)
+    }
+}
+
+/// Versions of a key are stored under its encoded key, which no other encoded
+/// key starts with, followed by the version as a big-endian `u64`.
+fn prefix(key: &[u8]) -> Vec<u8> {
+    let mut prefix = Vec::new();
+    key::encode_component(&mut prefix, key);
+    prefix
+}
+
+fn write_version(db: &Database, cf_name: &str, key: &[u8], value: &[u8]) -> Result<u64, Error> {
+    let prefix = prefix(key);
+    let _guard = db.locks.lock(cf_name, &prefix);
+    let version = match last_version(db, cf_name, &prefix, u64::MAX)? {
+        Some((version, _)) => version + 1,
+        None => 1,
+    };
+    db.rocksdb.put_cf(
+        &cf_handle(db, cf_name)?,
+        [&prefix[..], &version.to_be_bytes()].concat(),
+        value,
+    )?;
+    Ok(version)
+}
+
+/// Number and encoded value of a version, `None` for a delete.
+type Version = (u64, Option<Vec<u8>>);
+
+/// Returns the last version of the key encoded as `prefix` up to `version`.
+fn last_version(
+    db: &Database,
+    cf_name: &str,
+    prefix: &[u8],
+    version: u64,
+) -> Result<Option<Version>, Error> {
+    let mut raw = db
+        .rocksdb
+        .raw_iterator_cf_opt(&cf_handle(db, cf_name)?, iter::total_order_read_options());
+    raw.seek_for_prev([prefix, &version.to_be_bytes()].concat());
+    match (raw.key(), raw.value()) {
+        (Some(key), Some(value)) if key.starts_with(prefix) => {
+            let version = decode_version(&key[prefix.len()..])?;
+            Ok(Some((version, (!value.is_empty()).then(|| value.to_vec()))))
+        }
+        _ => Ok(raw.status().map(|()| None)?),
+    }
+}
+
+fn decode_version(bytes: &[u8]) -> Result<u64, Error> {
+    Ok(u64::from_be_bytes(
+        bytes.try_into().map_err(|_| Error::InvalidKey)?,
+    ))
+}
+
+fn decode<V: VersionedCollection>(bytes: &[u8]) -> Result<V, Error> {
+    rmp_serde::decode::from_slice(bytes).map_err(Error::RmpDecode)
+}
+
+fn cf_handle<'db>(
+    db: &'db Database,
+    cf_name: &str,
+) -> Result<rocksdb::ColumnFamilyRef<'db>, Error> {
+    db.rocksdb
+        .cf_handle(cf_name)
+        .ok_or(Error::CollectionNotRegistered)
+}
@@ -0,0 +1,124 @@
+use std::{
+    marker::PhantomData,
+    sync::{mpsc, Mutex, PoisonError},
+};
+
+use crate::{value, Collection, Error, Key};
+
+/// A committed change of one entry.
+pub struct Change<T: Collection> {
+    pub key: T::KeyType,
+    /// The new value, or `None` if the entry was deleted.
+    pub value: Option<T>,
+}
+
+/// Serialized change, as sent to the watchers.
+struct RawChange {
+    key: Box<[u8]>,
+    value: Option<Box<[u8]>>,
+}
+
+struct Subscription {
+    cf_name: &'static str,
+    /// Keys starting with `prefix` are watched, or only `prefix` itself if
+    /// `exact` is set.
+    prefix: Vec<u8>,
+    exact: bool,
+    sender: mpsc::Sender<Vec<RawChange>>,
+}
+
+impl Subscription {
+    fn matches(&self, cf_name: &str, key: &[u8]) -> bool {
+        cf_name == self.cf_name
+            && if self.exact {
+                key == self.prefix
+            } else {
+                key.starts_with(&self.prefix)
+            }
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct Watchers(Mutex<Vec<Subscription>>);
+
+impl Watchers {
+    pub(crate) fn subscribe<T: Collection>(&self, prefix: Vec<u8>, exact: bool) -> Watch<T> {
+        let (sender, receiver) = mpsc::channel();
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(Subscription {
+                cf_name: T::CF_NAME,
+                prefix,
+                exact,
+                sender,
+            });
+        Watch {
+            receiver,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Sends the `(CF, key, value)` changes of one commit to the interested
+    /// watchers, each getting them as a single batch.
+    pub(crate) fn notify<'a>(
+        &self,
+        changes: impl IntoIterator<Item = (&'a str, &'a [u8], Option<&'a [u8]>)> + Clone,
+    ) {
+        let mut subscriptions = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        // Watches that were dropped are only noticed, and removed, here.
+        subscriptions.retain(|subscription| {
+            let batch: Vec<RawChange> = changes
+                .clone()
+                .into_iter()
+                .filter(|(cf_name, key, _)| subscription.matches(cf_name, key))
+                .map(|(_, key, value)| RawChange {
+                    key: Box::from(key),
+                    value: value.map(Box::from),
+                })
+                .collect();
+            batch.is_empty() || subscription.sender.send(batch).is_ok()
+        });
+    }
+}
+
+/// Stream of the changes committed to watched entries of collection `T`.
+///
+/// Changes are grouped by commit: every item holds the matching changes of
+/// one `put`, `modify`, `delete`, batch or transaction, in commit order.
+/// Merges and range deletions are not reported. Iteration blocks until the
+/// next commit and ends once the database is closed.
+pub struct Watch<T> {
+    receiver: mpsc::Receiver<Vec<RawChange>>,
+    _marker: PhantomData<T>,
+}
+
+impl<T: Collection> Watch<T> {
+    /// Returns the next batch of changes if one is already pending.
+    pub fn try_next(&self) -> Option<Result<Vec<Change<T>>, Error>> {
+        self.receiver.try_recv().ok().map(decode)
+    }
+}
+
+impl<T: Collection> Iterator for Watch<T> {
+    type Item = Result<Vec<Change<T>>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.receiver.recv().ok().map(decode)
+    }
+}
+
+fn decode<T: Collection>(batch: Vec<RawChange>) -> Result<Vec<Change<T>>, Error> {
+    batch
+        .into_iter()
+        .map(|change| {
+            Ok(Change {
+                key: T::KeyType::deserialize(&change.key).ok_or(Error::InvalidKey)?,
+                value: match change.value {
+                    Some(value) => Some(value::decode(&change.key, &value)?),
+                    None => None,
+                },
+            })
+        })
+        .collect()
+}
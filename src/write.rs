@@ -0,0 +1,292 @@
+use std::{collections::HashMap, sync::PoisonError};
+
+use crate::{index, instrument, iter, value, Collection, Database, Error};
+
+/// Callback that stages the derived writes (index entries) for a change of
+/// `key` from `old` to `new`, both given in their serialized form.
+type Reindex = fn(
+    &Database,
+    &mut rocksdb::WriteBatch,
+    &mut index::UniqueClaims,
+    &[u8],
+    Option<&[u8]>,
+    Option<&[u8]>,
+) -> Result<(), Error>;
+
+/// How durably a write is committed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Durability {
+    /// Appended to the write-ahead log, which the OS writes out in its own
+    /// time. Survives a crash of the process, but not always one of the
+    /// machine.
+    #[default]
+    Default,
+    /// Appended to the write-ahead log, which is synced to disk before the
+    /// write returns.
+    Sync,
+    /// Not logged, so lost on a crash unless the memtables were flushed
+    /// first. Suits writes that can be redone, like bulk imports.
+    NoWal,
+}
+
+impl Durability {
+    fn write_options(self) -> rocksdb::WriteOptions {
+        let mut opts = rocksdb::WriteOptions::default();
+        match self {
+            Durability::Default => {}
+            Durability::Sync => opts.set_sync(true),
+            Durability::NoWal => opts.disable_wal(true),
+        }
+        opts
+    }
+}
+
+/// A single put or delete, serialized and ready to be committed.
+pub(crate) struct PendingWrite {
+    cf_name: &'static str,
+    key: Vec<u8>,
+    value: Option<Vec<u8>>,
+    reindex: Option<Reindex>,
+    /// Whether the collection has unique indexes.
+    unique: bool,
+}
+
+impl PendingWrite {
+    pub(crate) fn put<T: Collection>(key: &[u8], value: &T) -> Result<Self, Error> {
+        Ok(Self {
+            cf_name: T::CF_NAME,
+            key: key.to_vec(),
+            value: Some(value::encode(key, value)?),
+            reindex: Self::reindex::<T>(),
+            unique: !T::UNIQUE_INDEXES.is_empty(),
+        })
+    }
+
+    pub(crate) fn delete<T: Collection>(key: &[u8]) -> Self {
+        Self {
+            cf_name: T::CF_NAME,
+            key: key.to_vec(),
+            value: None,
+            reindex: Self::reindex::<T>(),
+            unique: !T::UNIQUE_INDEXES.is_empty(),
+        }
+    }
+
+    /// A write of internal metadata to the default column family.
+    pub(crate) fn metadata(key: Vec<u8>, value: Option<Vec<u8>>) -> Self {
+        Self {
+            cf_name: rocksdb::DEFAULT_COLUMN_FAMILY_NAME,
+            key,
+            value,
+            reindex: None,
+            unique: false,
+        }
+    }
+
+    /// The serialized value, or `None` for a delete.
+    pub(crate) fn value(&self) -> Option<&[u8]> {
+        self.value.as_deref()
+    }
+
+    /// The `(CF, key)` pair that must be locked while this write is committed.
+    pub(crate) fn lock_key(&self) -> (&str, &[u8]) {
+        (self.cf_name, &self.key)
+    }
+
+    fn reindex<T: Collection>() -> Option<Reindex> {
+        if T::INDEXES.is_empty() {
+            None
+        } else {
+            Some(index::reindex::<T>)
+        }
+    }
+}
+
+/// The value left under `key` of `cf_name` by the last of `writes` to it,
+/// or `None` if none of them writes it.
+pub(crate) fn staged<'w>(
+    writes: &'w [PendingWrite],
+    cf_name: &str,
+    key: &[u8],
+) -> Option<Option<&'w [u8]>> {
+    writes
+        .iter()
+        .rev()
+        .find(|write| write.lock_key() == (cf_name, key))
+        .map(PendingWrite::value)
+}
+
+/// Atomically applies `writes` in order, together with the index updates
+/// they imply.
+///
+/// The caller must hold the locks for every written key, so that the previous
+/// values read here to maintain indexes can't change before the batch is
+/// written.
+pub(crate) fn commit(db: &Database, writes: Vec<PendingWrite>) -> Result<(), Error> {
+    commit_opt(db, writes, Durability::Default)
+}
+
+/// Like [`commit`], with `durability`.
+pub(crate) fn commit_opt(
+    db: &Database,
+    writes: Vec<PendingWrite>,
+    durability: Durability,
+) -> Result<(), Error> {
+    let instrument = instrument::Commit::start(writes.len());
+    // Unique index values are checked against the stored index entries, which
+    // must not change until the batch is written.
+    let _unique_guard = writes.iter().any(|write| write.unique).then(|| {
+        db.unique_lock
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    });
+    let mut claims = index::UniqueClaims::default();
+    let mut batch = rocksdb::WriteBatch::default();
+    // Values written earlier in this batch, which later writes to the same key
+    // must see as their previous value.
+    let mut staged: HashMap<(&'static str, Vec<u8>), Option<Vec<u8>>> = HashMap::new();
+    // Writes to collections with hooks, with their previous values.
+    let mut hooked = Vec::new();
+    for write in &writes {
+        let cf = db
+            .rocksdb
+            .cf_handle(write.cf_name)
+            .ok_or(Error::CollectionNotRegistered)?;
+        let hooks = db.hooks.get(write.cf_name);
+        if write.reindex.is_some() || hooks.is_some() {
+            let staged_key = (write.cf_name, write.key.clone());
+            let old_value = match staged.get(&staged_key) {
+                Some(value) => value.clone(),
+                None => db
+                    .rocksdb
+                    .get_pinned_cf(&cf, &write.key)?
+                    .map(|value| value.to_vec()),
+            };
+            if let Some(reindex) = write.reindex {
+                reindex(
+                    db,
+                    &mut batch,
+                    &mut claims,
+                    &write.key,
+                    old_value.as_deref(),
+                    write.value.as_deref(),
+                )?;
+            }
+            if let Some(hooks) = hooks {
+                hooks.pre_write(db, &write.key, old_value.as_deref(), write.value.as_deref())?;
+                hooked.push((hooks, write, old_value));
+            }
+            staged.insert(staged_key, write.value.clone());
+        }
+        match &write.value {
+            Some(value) => batch.put_cf(&cf, &write.key, value),
+            None => batch.delete_cf(&cf, &write.key),
+        }
+    }
+    let audit = match &db.audit {
+        Some(audit) => Some(
+            audit.stage(
+                &db.rocksdb,
+                &mut batch,
+                writes
+                    .iter()
+                    .filter(|write| write.cf_name != rocksdb::DEFAULT_COLUMN_FAMILY_NAME)
+                    .map(|write| (write.cf_name, &write.key[..], write.value.as_deref())),
+            )?,
+        ),
+        None => None,
+    };
+    instrument.batch(batch.size_in_bytes());
+    db.rocksdb.write_opt(batch, &durability.write_options())?;
+    if let Some(audit) = audit {
+        audit.committed();
+    }
+    db.watchers.notify(
+        writes
+            .iter()
+            .map(|write| (write.cf_name, &write.key[..], write.value.as_deref())),
+    );
+    for (hooks, write, old_value) in hooked {
+        hooks.post_commit(db, &write.key, old_value.as_deref(), write.value.as_deref())?;
+    }
+    Ok(())
+}
+
+/// Deletes every entry of `T` with a key in `start..end`, or in `start..` if
+/// `end` is `None`.
+pub(crate) fn delete_range<T: Collection>(
+    db: &Database,
+    start: &[u8],
+    end: Option<&[u8]>,
+) -> Result<(), Error> {
+    let cf = db.cf_handle::<T>()?;
+    let mut opts = iter::total_order_read_options();
+    if let Some(end) = end {
+        opts.set_iterate_upper_bound(end);
+    }
+    let mut raw = db.rocksdb.raw_iterator_cf_opt(&cf, opts);
+    if !T::INDEXES.is_empty() {
+        // Index entries are keyed by value, so they can only be found
+        // through the records being deleted.
+        raw.seek(start);
+        let mut writes = Vec::new();
+        while let Some(key) = raw.key() {
+            writes.push(PendingWrite::delete::<T>(key));
+            raw.next();
+        }
+        raw.status()?;
+        let _guard = db
+            .locks
+            .lock_many(writes.iter().map(PendingWrite::lock_key));
+        return commit(db, writes);
+    }
+    let mut batch = rocksdb::WriteBatch::default();
+    match end {
+        Some(end) => batch.delete_range_cf(&cf, start, end),
+        None => stage_delete_from(&mut batch, raw, &cf, Some(start))?,
+    }
+    db.rocksdb.write(batch)?;
+    Ok(())
+}
+
+/// Deletes every entry of `T` and of its indexes.
+pub(crate) fn truncate<T: Collection>(db: &Database) -> Result<(), Error> {
+    let mut batch = rocksdb::WriteBatch::default();
+    let cf = db.cf_handle::<T>()?;
+    stage_delete_from(&mut batch, db.rocksdb.raw_iterator_cf(&cf), &cf, None)?;
+    for index in T::INDEXES {
+        let cf = index::index_cf::<T>(db, index)?;
+        stage_delete_from(&mut batch, db.rocksdb.raw_iterator_cf(&cf), &cf, None)?;
+    }
+    db.rocksdb.write(batch)?;
+    Ok(())
+}
+
+/// Stages the deletion of every key of `cf` from `start` on, or of every key
+/// if `start` is `None`, using `raw`, an iterator over `cf`, to find the first
+/// and last keys.
+pub(crate) fn stage_delete_from(
+    batch: &mut rocksdb::WriteBatch,
+    mut raw: rocksdb::DBRawIterator<'_>,
+    cf: &rocksdb::ColumnFamilyRef,
+    start: Option<&[u8]>,
+) -> Result<(), Error> {
+    match start {
+        Some(start) => raw.seek(start),
+        None => raw.seek_to_first(),
+    }
+    let Some(first) = raw.key().map(<[u8]>::to_vec) else {
+        return Ok(raw.status()?);
+    };
+    // RocksDB ranges always have an exclusive end, so the last key is
+    // deleted on its own.
+    raw.seek_to_last();
+    match raw.key() {
+        Some(last) => {
+            batch.delete_range_cf(cf, first.as_slice(), last);
+            batch.delete_cf(cf, last);
+            Ok(())
+        }
+        None => Ok(raw.status()?),
+    }
+}